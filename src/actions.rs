@@ -0,0 +1,259 @@
+use crate::config::{Action, ActionMode, Config};
+use crate::project::Project;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use termion::color;
+
+const CLANG_FORMAT: &str = "clang-format";
+const CLANG_TIDY: &str = "clang-tidy";
+
+pub struct Job {
+    pub project: String,
+    pub target: String,
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+pub struct JobResult {
+    pub project: String,
+    pub target: String,
+    pub success: bool,
+    pub output: String,
+}
+
+fn run_job(job: Job) -> JobResult {
+    let (success, output) = match Command::new(&job.program).args(&job.args).output() {
+        Ok(output) => {
+            let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+            text.push_str(&String::from_utf8_lossy(&output.stderr));
+            (output.status.success(), text)
+        }
+        Err(e) => (false, format!("failed to run {}: {}\n", job.program, e)),
+    };
+
+    JobResult {
+        project: job.project,
+        target: job.target,
+        success,
+        output,
+    }
+}
+
+pub fn run_parallel(jobs: Vec<Job>) -> Vec<JobResult> {
+    let job_count = jobs.len();
+    let queue = Arc::new(Mutex::new(jobs.into_iter().enumerate().collect::<Vec<_>>()));
+    let results = Arc::new(Mutex::new(vec![]));
+
+    // Spawn a worker per core which keeps taking jobs until the queue is empty
+    let worker_count = thread::available_parallelism()
+        .map(|x| x.get())
+        .unwrap_or(1)
+        .min(job_count.max(1));
+    let workers: Vec<_> = (0..worker_count)
+        .map(|_| {
+            let queue = Arc::clone(&queue);
+            let results = Arc::clone(&results);
+
+            thread::spawn(move || loop {
+                let job = queue.lock().unwrap().pop();
+                match job {
+                    Some((index, job)) => results.lock().unwrap().push((index, run_job(job))),
+                    None => break,
+                }
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    // Report the results in the order the jobs were given
+    let mut results = Arc::try_unwrap(results).ok().unwrap().into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+pub fn print_report(action: &str, results: &[JobResult]) -> bool {
+    let failures = results.iter().filter(|x| !x.success).count();
+
+    for result in results {
+        if !result.success {
+            println!(
+                "{}{} failed{} for {} ({})",
+                color::Fg(color::Red),
+                action,
+                color::Fg(color::Reset),
+                result.target,
+                result.project
+            );
+        } else if !result.output.is_empty() {
+            // Successful runs can still have something to say, like warnings
+            println!("{} for {} ({})", action, result.target, result.project);
+        }
+        print!("{}", result.output);
+    }
+
+    println!("{}: {} checked, {} failed", action, results.len(), failures);
+
+    failures == 0
+}
+
+fn get_all_files(project: &Project) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = project
+        .get_source_files()
+        .iter()
+        .map(|x| x.path().to_path_buf())
+        .collect();
+    files.append(&mut project.get_header_files());
+    files
+}
+
+pub fn format_jobs(projects: &[&Project], check: bool) -> Vec<Job> {
+    let mut jobs = vec![];
+    let mut seen = vec![];
+
+    for project in projects {
+        for file in get_all_files(project) {
+            // Files shared between projects only have to be formatted once
+            if seen.contains(&file) {
+                continue;
+            }
+            seen.push(file.clone());
+
+            // In check mode any formatting difference is reported as an error
+            let mut args: Vec<String> = if check {
+                vec![String::from("--dry-run"), String::from("--Werror")]
+            } else {
+                vec![String::from("-i")]
+            };
+            args.push(file.to_str().unwrap().to_string());
+
+            jobs.push(Job {
+                project: project.name.clone(),
+                target: file.to_str().unwrap().to_string(),
+                program: String::from(CLANG_FORMAT),
+                args,
+            });
+        }
+    }
+
+    jobs
+}
+
+pub fn tidy_jobs(projects: &[&Project], config: &Config) -> Result<Vec<Job>, String> {
+    let mut jobs = vec![];
+
+    for project in projects {
+        let source_files = project.get_all_source_files(config);
+
+        // Pass the same flags the file is compiled with
+        for command in project.get_compile_commands(&source_files[..], config)? {
+            let mut args = vec![command.file.clone(), String::from("--")];
+            args.append(&mut command.flags());
+
+            jobs.push(Job {
+                project: project.name.clone(),
+                target: command.source_file.path().to_str().unwrap().to_string(),
+                program: String::from(CLANG_TIDY),
+                args,
+            });
+        }
+    }
+
+    Ok(jobs)
+}
+
+fn shell_quote(value: &str) -> String {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_./=+:,@".contains(c))
+    {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+fn quote_all(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|x| shell_quote(x))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn expand(template: &str, project: &Project, file: Option<&PathBuf>, files: &[PathBuf]) -> String {
+    let include_flags: Vec<_> = project
+        .include
+        .iter()
+        .flatten()
+        .map(|x| format!("-I{}", x))
+        .collect();
+    let defines: Vec<_> = project
+        .defines
+        .iter()
+        .flatten()
+        .map(|x| format!("-D{}", x))
+        .collect();
+    let files: Vec<_> = files
+        .iter()
+        .map(|x| x.to_str().unwrap().to_string())
+        .collect();
+
+    let mut command = template
+        .replace("{project}", &shell_quote(&project.name))
+        .replace("{files}", &quote_all(&files))
+        .replace("{include_flags}", &quote_all(&include_flags))
+        .replace("{defines}", &quote_all(&defines));
+
+    if let Some(file) = file {
+        command = command.replace("{file}", &shell_quote(file.to_str().unwrap()));
+    }
+
+    command
+}
+
+pub fn custom_jobs(action: &Action, projects: &[&Project]) -> Vec<Job> {
+    let mut jobs = vec![];
+
+    for project in projects {
+        // Only run on the projects the action is meant for
+        if let Some(names) = &action.projects {
+            if !names.contains(&project.name) {
+                continue;
+            }
+        }
+
+        let files = get_all_files(project);
+
+        let mut push_job = |target: String, command: String| {
+            jobs.push(Job {
+                project: project.name.clone(),
+                target,
+                program: String::from("sh"),
+                args: vec![String::from("-c"), command],
+            })
+        };
+
+        match action.mode {
+            Some(ActionMode::PerProject) => push_job(
+                project.name.clone(),
+                expand(&action.command, project, None, &files),
+            ),
+            Some(ActionMode::PerFile) | None => {
+                for file in &files {
+                    push_job(
+                        file.to_str().unwrap().to_string(),
+                        expand(&action.command, project, Some(file), &files),
+                    );
+                }
+            }
+        }
+    }
+
+    jobs
+}
@@ -5,6 +5,8 @@ use std::hash::Hash;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
+pub const CACHE_PATH: &str = ".buldr_cache";
+
 #[derive(Serialize, Deserialize)]
 struct CacheData {
     // Map which contains the last compiled time (secs since epoch) of each file in the project
@@ -25,7 +27,7 @@ impl Cache {
         std::fs::canonicalize(build_file).unwrap().hash(&mut hasher);
 
         // Create the cache file the temp directory
-        let cache_file = PathBuf::from(".buldr_cache");
+        let cache_file = PathBuf::from(CACHE_PATH);
 
         let data = if cache_file.exists() {
             // If the cache file exist load the data from there
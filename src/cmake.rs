@@ -0,0 +1,375 @@
+use crate::config::BuildConfig;
+use crate::project::ProjectKind;
+use crate::toolchain;
+use std::fmt::Write;
+
+// 3.16 is the first version with precompiled headers and unity builds
+const CMAKE_MINIMUM_VERSION: &str = "3.16";
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('$', "\\$")
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", escape(value))
+}
+
+fn quote_all<T: AsRef<str>>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|x| quote(x.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn pkg_config_prefix(name: &str) -> String {
+    // The variables pkg_check_modules sets are named after the prefix
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{}_PKG", name.to_uppercase())
+}
+
+pub struct CMakeExport {
+    pub contents: String,
+    pub warnings: Vec<String>,
+}
+
+pub fn generate(config: &BuildConfig) -> CMakeExport {
+    let mut output = String::new();
+    let mut warnings = vec![];
+
+    // Name the CMake project after the default project if there is one
+    let name = config
+        .projects
+        .iter()
+        .find(|x| x.default == Some(true))
+        .or_else(|| config.projects.first())
+        .map(|x| x.name.as_str())
+        .unwrap_or("buldr");
+
+    writeln!(output, "# Generated by buldr").unwrap();
+    writeln!(
+        output,
+        "cmake_minimum_required(VERSION {})",
+        CMAKE_MINIMUM_VERSION
+    )
+    .unwrap();
+    writeln!(output, "project({} C CXX)", quote(name)).unwrap();
+    if config
+        .projects
+        .iter()
+        .any(|x| matches!(x.kind, ProjectKind::Test))
+    {
+        writeln!(output, "enable_testing()").unwrap();
+    }
+    if config
+        .projects
+        .iter()
+        .any(|x| x.pkg_config.iter().flatten().next().is_some())
+    {
+        writeln!(output, "find_package(PkgConfig REQUIRED)").unwrap();
+    }
+    writeln!(output).unwrap();
+
+    // The toolchain is chosen when configuring CMake, not in the CMakeLists.txt
+    warnings.push(format!(
+        "compiler '{}', linker '{}' and packer '{}' are not exported, pass them with -DCMAKE_C_COMPILER and friends",
+        config.config.compiler(),
+        config.config.linker(),
+        config.config.packer()
+    ));
+
+    if let Some(args) = &config.config.compiler_opts {
+        if !args.is_empty() {
+            writeln!(output, "add_compile_options({})", quote_all(args)).unwrap();
+        }
+    }
+
+    if let Some(args) = &config.config.linker_opts {
+        if !args.is_empty() {
+            writeln!(output, "add_link_options({})", quote_all(args)).unwrap();
+        }
+    }
+
+    if let Some(args) = &config.config.packer_opts {
+        if !args.is_empty() {
+            warnings.push(format!("packer_opts {:?} can't be translated", args));
+        }
+    }
+
+    // Put the artifacts in the same place buldr does, CMake decides where the objects go
+    writeln!(
+        output,
+        "set(CMAKE_RUNTIME_OUTPUT_DIRECTORY \"${{CMAKE_SOURCE_DIR}}/{}\")",
+        escape(&config.config.bin)
+    )
+    .unwrap();
+    writeln!(
+        output,
+        "set(CMAKE_ARCHIVE_OUTPUT_DIRECTORY \"${{CMAKE_SOURCE_DIR}}/{}\")",
+        escape(&config.config.bin)
+    )
+    .unwrap();
+    warnings.push(format!(
+        "obj directory '{}' is not exported, CMake manages object files itself",
+        config.config.obj
+    ));
+
+    for project in &config.projects {
+        writeln!(output).unwrap();
+
+        // Generated sources are listed even when they don't exist yet, CMake generates them first
+        let mut source_files: Vec<_> = project
+            .get_source_files()
+            .iter()
+            .map(|x| x.path().to_str().unwrap().to_string())
+            .collect();
+        source_files.extend(
+            project
+                .get_generated_sources(&config.config)
+                .iter()
+                .map(|x| x.to_str().unwrap().to_string()),
+        );
+
+        // The generators write to the same place as with buldr, relative to the source tree
+        for generator in project.generate.iter().flatten() {
+            let (inputs, outputs, command) = project.expand_generator(generator, &config.config);
+            let mut directories: Vec<_> = outputs
+                .iter()
+                .map(|x| x.parent().unwrap().to_str().unwrap())
+                .collect();
+            directories.sort_unstable();
+            directories.dedup();
+            let outputs: Vec<_> = outputs
+                .iter()
+                .map(|x| format!("\"${{CMAKE_SOURCE_DIR}}/{}\"", escape(x.to_str().unwrap())))
+                .collect();
+
+            write!(
+                output,
+                "file(MAKE_DIRECTORY {})\nadd_custom_command(OUTPUT {} COMMAND sh -c {} WORKING_DIRECTORY \"${{CMAKE_SOURCE_DIR}}\"",
+                quote_all(&directories),
+                outputs.join(" "),
+                quote(&command)
+            )
+            .unwrap();
+            if !inputs.is_empty() {
+                let inputs: Vec<_> = inputs.iter().map(|x| x.to_str().unwrap()).collect();
+                write!(output, " DEPENDS {}", quote_all(&inputs)).unwrap();
+            }
+            writeln!(output, " VERBATIM)").unwrap();
+        }
+
+        if source_files.is_empty() {
+            warnings.push(format!(
+                "project '{}' has no source files, CMake requires at least one",
+                project.name
+            ));
+        }
+
+        match project.kind {
+            ProjectKind::Executable => writeln!(
+                output,
+                "add_executable({} {})",
+                project.name,
+                quote_all(&source_files)
+            ),
+            // Tests aren't part of the default build, just like in buldr
+            ProjectKind::Test => writeln!(
+                output,
+                "add_executable({} EXCLUDE_FROM_ALL {})\nadd_test(NAME {} COMMAND {})",
+                project.name,
+                quote_all(&source_files),
+                project.name,
+                project.name
+            ),
+            ProjectKind::Library => writeln!(
+                output,
+                "add_library({} STATIC {})",
+                project.name,
+                quote_all(&source_files)
+            ),
+        }
+        .unwrap();
+
+        // Generated headers can be included like any other header
+        let mut include_dirs = project.include.clone().unwrap_or_default();
+        for path in project.get_generated_files(&config.config) {
+            let dir = path.parent().unwrap().to_str().unwrap().to_string();
+            if !include_dirs.contains(&dir) {
+                include_dirs.push(dir);
+            }
+        }
+
+        if !include_dirs.is_empty() {
+            writeln!(
+                output,
+                "target_include_directories({} PRIVATE {})",
+                project.name,
+                quote_all(&include_dirs)
+            )
+            .unwrap();
+        }
+
+        if let Some(defines) = &project.defines {
+            if !defines.is_empty() {
+                writeln!(
+                    output,
+                    "target_compile_definitions({} PRIVATE {})",
+                    project.name,
+                    quote_all(defines)
+                )
+                .unwrap();
+            }
+        }
+
+        // The high-level options are translated for the compiler of the build file
+        let options = project.get_options(&config.config);
+        let compile_flags =
+            options.get_compile_flags(toolchain::detect_family(config.config.compiler()));
+        if !compile_flags.is_empty() {
+            writeln!(
+                output,
+                "target_compile_options({} PRIVATE {})",
+                project.name,
+                quote_all(&compile_flags)
+            )
+            .unwrap();
+        }
+
+        // The standard only applies to the sources of its own language
+        if let Some(standard) = &options.language_standard {
+            let language = if standard.contains("++") { "CXX" } else { "C" };
+            writeln!(
+                output,
+                "target_compile_options({} PRIVATE \"$<$<COMPILE_LANGUAGE:{}>:-std={}>\")",
+                project.name,
+                language,
+                escape(standard)
+            )
+            .unwrap();
+        }
+
+        let link_flags = options.get_link_flags(toolchain::detect_family(config.config.linker()));
+        if !link_flags.is_empty() && !matches!(project.kind, ProjectKind::Library) {
+            writeln!(
+                output,
+                "target_link_options({} PRIVATE {})",
+                project.name,
+                quote_all(&link_flags)
+            )
+            .unwrap();
+        }
+
+        if let Some(launcher) = project.get_compiler_launcher(&config.config) {
+            let launcher: Vec<_> = launcher.split_whitespace().collect();
+            writeln!(
+                output,
+                "set_target_properties({} PROPERTIES C_COMPILER_LAUNCHER {} CXX_COMPILER_LAUNCHER {})",
+                project.name,
+                quote(&launcher.join(";")),
+                quote(&launcher.join(";"))
+            )
+            .unwrap();
+        }
+
+        if let Some(header) = &project.precompiled_header {
+            writeln!(
+                output,
+                "target_precompile_headers({} PRIVATE {})",
+                project.name,
+                quote(header.to_str().unwrap())
+            )
+            .unwrap();
+        }
+
+        if project.unity == Some(true) {
+            write!(
+                output,
+                "set_target_properties({} PROPERTIES UNITY_BUILD ON",
+                project.name
+            )
+            .unwrap();
+            if let Some(batch_size) = project.unity_batch_size {
+                write!(output, " UNITY_BUILD_BATCH_SIZE {}", batch_size).unwrap();
+            }
+            writeln!(output, ")").unwrap();
+        }
+
+        // Dependencies are other targets, links are plain system libraries
+        let mut libraries = vec![];
+        if let Some(depends) = &project.depends {
+            libraries.extend(depends.iter().cloned());
+        }
+        if let Some(links) = &project.links {
+            libraries.extend(links.iter().cloned());
+        }
+
+        if !libraries.is_empty() {
+            writeln!(
+                output,
+                "target_link_libraries({} PRIVATE {})",
+                project.name,
+                quote_all(&libraries)
+            )
+            .unwrap();
+        }
+
+        // System dependencies of a library reach whatever links it, just like with buldr
+        if let Some(requirements) = project.pkg_config.as_ref().filter(|x| !x.is_empty()) {
+            let prefix = pkg_config_prefix(&project.name);
+            let scope = match project.kind {
+                ProjectKind::Library => "PUBLIC",
+                ProjectKind::Executable | ProjectKind::Test => "PRIVATE",
+            };
+            writeln!(
+                output,
+                "pkg_check_modules({} REQUIRED IMPORTED_TARGET {})\ntarget_link_libraries({} {} PkgConfig::{})",
+                prefix,
+                quote_all(requirements),
+                project.name,
+                scope,
+                prefix
+            )
+            .unwrap();
+        }
+
+        // The hooks run around buldr's own build steps, which CMake has no equivalent for
+        for (name, hooks) in [
+            ("pre_build", &project.pre_build),
+            ("post_build", &project.post_build),
+        ] {
+            if let Some(hooks) = hooks.as_ref().filter(|x| !x.is_empty()) {
+                warnings.push(format!(
+                    "{} hooks {:?} of project '{}' can't be translated",
+                    name, hooks, project.name
+                ));
+            }
+        }
+
+        if project.default == Some(true) {
+            warnings.push(format!(
+                "default project '{}' has no CMake equivalent, all targets are built by default",
+                project.name
+            ));
+        }
+    }
+
+    // Flag everything that was lost in the generated file as well
+    if !warnings.is_empty() {
+        writeln!(output).unwrap();
+        for warning in &warnings {
+            writeln!(output, "# buldr: {}", warning).unwrap();
+        }
+    }
+
+    CMakeExport {
+        contents: output,
+        warnings,
+    }
+}
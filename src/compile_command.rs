@@ -1,31 +1,64 @@
+use crate::toolchain;
 use serde_derive::Serialize;
-use std::path::PathBuf;
-use std::process::Command;
+use std::path::{Path, PathBuf};
 use walkdir::DirEntry;
 
 #[derive(Debug, Serialize)]
 pub struct CompileCommand {
     pub directory: PathBuf,
-    pub command: String,
+    // The first argument is always the compiler itself
     pub arguments: Vec<String>,
     pub file: String,
+    pub output: String,
     #[serde(skip_serializing)]
     pub source_file: DirEntry,
+    // Only used to run the compiler, tools reading the database need the real compiler
+    #[serde(skip_serializing)]
+    pub launcher: Option<String>,
 }
 
 impl CompileCommand {
-    pub fn new(directory: PathBuf, command: &str, source_file: &DirEntry) -> Self {
+    pub fn new(
+        directory: PathBuf,
+        compiler: &str,
+        source_file: &DirEntry,
+        output_file: &Path,
+    ) -> Self {
         Self {
             directory,
-            command: command.to_string(),
-            arguments: vec![],
+            arguments: vec![compiler.to_string()],
             file: std::fs::canonicalize(source_file.path())
                 .unwrap()
                 .to_str()
                 .unwrap()
                 .to_string(),
+            output: output_file.to_str().unwrap().to_string(),
             source_file: source_file.clone(),
+            launcher: None,
+        }
+    }
+
+    pub fn compiler(&self) -> &str {
+        &self.arguments[0]
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.arguments[1..]
+    }
+
+    pub fn flags(&self) -> Vec<String> {
+        // Everything except the input and output of this single compilation
+        let mut flags = vec![];
+        let mut args = self.args().iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-c" | "-o" => {
+                    args.next();
+                }
+                _ => flags.push(arg.clone()),
+            }
         }
+        flags
     }
 
     pub fn push_args<T: ToString>(&mut self, args: &[T]) {
@@ -34,15 +67,22 @@ impl CompileCommand {
         }
     }
 
-    pub fn execute(&mut self) -> Result<(), String> {
-        let mut command = Command::new(&self.command);
-        command.args(&self.arguments);
+    pub fn execute(&mut self) -> Result<String, String> {
+        let mut command = toolchain::compiler_command(self.launcher.as_deref(), self.compiler());
+        command.args(self.args());
 
-        let output = command.output().expect("Failed to execute compile command");
+        let output = command.output().map_err(|e| {
+            format!(
+                "failed to run {}: {}\n",
+                command.get_program().to_string_lossy(),
+                e
+            )
+        })?;
+        let stderr = String::from_utf8(output.stderr).unwrap();
         if output.status.success() {
-            Ok(())
+            Ok(stderr)
         } else {
-            Err(String::from_utf8(output.stderr).unwrap())
+            Err(stderr)
         }
     }
 }
@@ -1,21 +1,152 @@
+use crate::options::BuildOptions;
 use crate::project::Project;
-use serde_derive::Deserialize;
+use crate::remote_cache::RemoteCacheMode;
+use crate::toolchain;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Config {
-    pub compiler: String,
+    pub compiler: Option<String>,
+    pub compiler_launcher: Option<String>,
     pub compiler_opts: Option<Vec<String>>,
-    pub linker: String,
+    pub linker: Option<String>,
     pub linker_opts: Option<Vec<String>>,
-    pub packer: String,
+    pub packer: Option<String>,
     pub packer_opts: Option<Vec<String>>,
     pub bin: String,
     pub obj: String,
+    pub object_cache: Option<bool>,
+    pub object_cache_dir: Option<PathBuf>,
+    // In megabytes
+    pub object_cache_size: Option<u64>,
+    pub remote_cache: Option<String>,
+    pub remote_cache_mode: Option<RemoteCacheMode>,
+    #[serde(flatten)]
+    pub options: BuildOptions,
+    // The triple given with --target, not part of the build file
+    #[serde(skip)]
+    pub target: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+impl Config {
+    // The tools are always set once the build file is loaded, they are only optional in the file
+    pub fn compiler(&self) -> &str {
+        self.compiler.as_deref().unwrap_or_default()
+    }
+
+    pub fn linker(&self) -> &str {
+        self.linker.as_deref().unwrap_or_default()
+    }
+
+    pub fn packer(&self) -> &str {
+        self.packer.as_deref().unwrap_or_default()
+    }
+
+    pub fn detect_toolchain(&mut self, cpp: bool) {
+        if self.compiler.is_some() && self.linker.is_some() && self.packer.is_some() {
+            return;
+        }
+
+        // The compiler driver links as well, so an unset linker follows a configured compiler
+        let toolchain = toolchain::detect(cpp);
+        let linker = match &self.compiler {
+            Some(compiler) => compiler.clone(),
+            None => toolchain.linker,
+        };
+        self.compiler.get_or_insert(toolchain.compiler);
+        self.linker.get_or_insert(linker);
+        self.packer.get_or_insert(toolchain.packer);
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Target {
+    pub compiler: Option<String>,
+    pub compiler_opts: Option<Vec<String>>,
+    pub linker: Option<String>,
+    pub linker_opts: Option<Vec<String>>,
+    pub packer: Option<String>,
+    pub sysroot: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionMode {
+    PerFile,
+    PerProject,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Action {
+    pub name: String,
+    pub command: String,
+    pub mode: Option<ActionMode>,
+    pub projects: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct BuildConfig {
     pub config: Config,
     #[serde(rename = "project")]
     pub projects: Vec<Project>,
+    #[serde(rename = "action", default, skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<Action>,
+    #[serde(rename = "target", default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub targets: BTreeMap<String, Target>,
+}
+
+impl BuildConfig {
+    pub fn uses_cpp(&self) -> bool {
+        self.projects.iter().any(|x| x.uses_cpp())
+    }
+
+    pub fn apply_target(&mut self, triple: &str) -> bool {
+        let target = match self.targets.get(triple) {
+            Some(target) => target,
+            None => return false,
+        };
+        let config = &mut self.config;
+
+        // A target compiler also links, unless the target says otherwise
+        if let Some(compiler) = &target.compiler {
+            config.compiler = Some(compiler.clone());
+            config.linker = Some(target.linker.clone().unwrap_or_else(|| compiler.clone()));
+        } else if let Some(linker) = &target.linker {
+            config.linker = Some(linker.clone());
+        }
+        if let Some(packer) = &target.packer {
+            config.packer = Some(packer.clone());
+        }
+
+        let cpp = self.projects.iter().any(|x| x.uses_cpp());
+        let config = &mut self.config;
+        config.detect_toolchain(cpp);
+
+        // Only clang can be told the target, GCC is built for a single one
+        let mut flags = vec![];
+        if toolchain::detect_family(config.compiler()) == toolchain::CompilerFamily::Clang {
+            flags.push(format!("--target={}", triple));
+        }
+        if let Some(sysroot) = &target.sysroot {
+            flags.push(format!("--sysroot={}", sysroot.to_str().unwrap()));
+        }
+
+        let compiler_opts = config.compiler_opts.get_or_insert_with(Vec::new);
+        compiler_opts.extend(flags.iter().cloned());
+        compiler_opts.extend(target.compiler_opts.iter().flatten().cloned());
+
+        let linker_opts = config.linker_opts.get_or_insert_with(Vec::new);
+        linker_opts.extend(flags);
+        linker_opts.extend(target.linker_opts.iter().flatten().cloned());
+
+        // Host and target builds live next to each other
+        let join = |dir: &str| Path::new(dir).join(triple).to_str().unwrap().to_string();
+        config.bin = join(&config.bin);
+        config.obj = join(&config.obj);
+        config.target = Some(triple.to_string());
+
+        true
+    }
 }
@@ -0,0 +1,165 @@
+use crate::config::{BuildConfig, Config};
+use crate::project::{Project, ProjectKind};
+use crate::toolchain;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+pub struct CreateOptions {
+    pub name: Option<String>,
+    pub kind: ProjectKind,
+}
+
+fn is_empty_dir(dir: &Path) -> Result<bool, std::io::Error> {
+    // Hidden files like .git don't count as project contents
+    for entry in std::fs::read_dir(dir)? {
+        if !entry?.file_name().to_str().unwrap_or(".").starts_with('.') {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+fn get_extensions(dir: &Path) -> Vec<String> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            e.path()
+                .extension()
+                .and_then(|x| x.to_str())
+                .map(|x| x.to_string())
+        })
+        .collect()
+}
+
+fn find_compiler(compilers: &[&str]) -> String {
+    toolchain::find_first_program(compilers).unwrap_or_else(|| {
+        eprintln!("warning: no compiler found, using {}", compilers[0]);
+        compilers[0].to_string()
+    })
+}
+
+fn write_skeleton(dir: &Path, name: &str, kind: &ProjectKind) -> Result<(), std::io::Error> {
+    std::fs::create_dir_all(dir.join("src"))?;
+
+    match kind {
+        ProjectKind::Executable | ProjectKind::Test => std::fs::write(
+            dir.join("src/main.c"),
+            "#include <stdio.h>\n\nint main(void) {\n    printf(\"Hello, world!\\n\");\n    return 0;\n}\n",
+        ),
+        ProjectKind::Library => {
+            std::fs::create_dir_all(dir.join("include"))?;
+
+            let guard = format!("{}_H", name.to_uppercase().replace('-', "_"));
+            let function = name.replace('-', "_");
+
+            std::fs::write(
+                dir.join("include").join(format!("{}.h", name)),
+                format!(
+                    "#ifndef {guard}\n#define {guard}\n\nint {function}_answer(void);\n\n#endif\n",
+                    guard = guard,
+                    function = function
+                ),
+            )?;
+            std::fs::write(
+                dir.join("src").join(format!("{}.c", name)),
+                format!(
+                    "#include \"{name}.h\"\n\nint {function}_answer(void) {{\n    return 42;\n}}\n",
+                    name = name,
+                    function = function
+                ),
+            )
+        }
+    }
+}
+
+pub fn create(dir: &Path, options: CreateOptions) -> Result<BuildConfig, std::io::Error> {
+    // Default to the name of the directory
+    let name = match options.name {
+        Some(name) => name,
+        None => std::fs::canonicalize(dir)?
+            .file_name()
+            .and_then(|x| x.to_str())
+            .unwrap_or("project")
+            .to_string(),
+    };
+
+    // Give empty directories something to build
+    if is_empty_dir(dir)? {
+        write_skeleton(dir, &name, &options.kind)?;
+    }
+
+    // Use the conventional directories when they exist, otherwise the sources live next to
+    // the build file
+    let src = if dir.join("src").is_dir() {
+        PathBuf::from("src/")
+    } else {
+        PathBuf::from("./")
+    };
+    let include = if dir.join("include").is_dir() {
+        Some(vec![String::from("include/")])
+    } else {
+        None
+    };
+
+    // Pick the languages and compilers that are actually installed. C sources are compiled by
+    // the C driver even next to C++ ones, only linking needs the C++ driver
+    let found = get_extensions(&dir.join(&src));
+    let cpp = found
+        .iter()
+        .any(|x| toolchain::CPP_EXTENSIONS.contains(&x.as_str()));
+    let mut extensions = vec![];
+    if !cpp || found.iter().any(|x| x == "c") {
+        extensions.push("c");
+    }
+    if cpp {
+        extensions.extend(toolchain::CPP_EXTENSIONS);
+    }
+    let compiler = find_compiler(toolchain::C_COMPILERS);
+    let linker = if cpp {
+        find_compiler(toolchain::CPP_COMPILERS)
+    } else {
+        compiler.clone()
+    };
+
+    let default = match options.kind {
+        ProjectKind::Executable => Some(true),
+        ProjectKind::Library | ProjectKind::Test => None,
+    };
+
+    // The headers of a library are the ones other projects will use
+    let public_headers = match options.kind {
+        ProjectKind::Library => include
+            .as_ref()
+            .map(|x| x.iter().map(PathBuf::from).collect()),
+        _ => None,
+    };
+
+    Ok(BuildConfig {
+        config: Config {
+            compiler: Some(compiler),
+            compiler_opts: Some(vec![String::from("-Wall")]),
+            linker: Some(linker),
+            linker_opts: Some(vec![]),
+            packer: Some(String::from("ar")),
+            packer_opts: Some(vec![]),
+            bin: String::from("bin/"),
+            obj: String::from("obj/"),
+            ..Default::default()
+        },
+        projects: vec![Project {
+            name,
+            kind: options.kind,
+            src: vec![src],
+            extensions: Some(extensions.iter().map(|x| x.to_string()).collect()),
+            include,
+            default,
+            public_headers,
+            ..Default::default()
+        }],
+        actions: vec![],
+        targets: BTreeMap::new(),
+    })
+}
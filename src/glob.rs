@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+fn is_pattern(component: &str) -> bool {
+    component.contains(['*', '?'])
+}
+
+fn matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            // `**/` also matches no directories at all
+            let rest = &pattern[2..];
+            let rest_without_slash = rest.strip_prefix(&['/']).unwrap_or(rest);
+            matches(rest_without_slash, text) || (0..text.len()).any(|i| matches(rest, &text[i..]))
+        }
+        Some('*') => {
+            // A single star never crosses a directory boundary
+            let rest = &pattern[1..];
+            for i in 0..=text.len() {
+                if matches(rest, &text[i..]) {
+                    return true;
+                }
+                if i < text.len() && text[i] == '/' {
+                    break;
+                }
+            }
+            false
+        }
+        Some('?') => !text.is_empty() && text[0] != '/' && matches(&pattern[1..], &text[1..]),
+        Some(c) => !text.is_empty() && text[0] == *c && matches(&pattern[1..], &text[1..]),
+    }
+}
+
+pub fn expand(pattern: &str) -> Vec<PathBuf> {
+    // Plain paths don't have to be searched for
+    if !is_pattern(pattern) {
+        return Some(PathBuf::from(pattern))
+            .filter(|x| x.exists())
+            .into_iter()
+            .collect();
+    }
+
+    // Only walk the directory below the part of the pattern without wildcards
+    let base: PathBuf = Path::new(pattern)
+        .components()
+        .take_while(|x| !is_pattern(x.as_os_str().to_str().unwrap()))
+        .collect();
+    let base = if base.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        base
+    };
+
+    let pattern: Vec<char> = pattern.trim_start_matches("./").chars().collect();
+
+    let mut paths: Vec<PathBuf> = WalkDir::new(&base)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.path().to_path_buf())
+        .filter(|path| {
+            let text: Vec<char> = path
+                .to_str()
+                .unwrap()
+                .trim_start_matches("./")
+                .chars()
+                .collect();
+            matches(&pattern, &text)
+        })
+        .collect();
+
+    paths.sort();
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str, text: &str) -> bool {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let text: Vec<char> = text.chars().collect();
+        matches(&pattern, &text)
+    }
+
+    #[test]
+    fn literal() {
+        assert!(glob("src/main.c", "src/main.c"));
+        assert!(!glob("src/main.c", "src/main.cc"));
+        assert!(!glob("src/main.c", "src/main"));
+    }
+
+    #[test]
+    fn star_stays_in_directory() {
+        assert!(glob("src/*.c", "src/main.c"));
+        assert!(glob("src/*.c", "src/.c"));
+        assert!(glob("*", "main.c"));
+        assert!(!glob("src/*.c", "src/sub/main.c"));
+        assert!(!glob("*.c", "src/main.c"));
+        assert!(glob("src/*/*.c", "src/sub/main.c"));
+    }
+
+    #[test]
+    fn double_star_crosses_directories() {
+        assert!(glob("src/**/*.c", "src/main.c"));
+        assert!(glob("src/**/*.c", "src/a/main.c"));
+        assert!(glob("src/**/*.c", "src/a/b/main.c"));
+        assert!(glob("**/*.proto", "main.proto"));
+        assert!(glob("**/*.proto", "a/b/main.proto"));
+        assert!(!glob("src/**/*.c", "lib/main.c"));
+        assert!(!glob("src/**/*.c", "src/a/main.h"));
+    }
+
+    #[test]
+    fn question_mark() {
+        assert!(glob("src/?.c", "src/a.c"));
+        assert!(!glob("src/?.c", "src/ab.c"));
+        assert!(!glob("src/?.c", "src/.c"));
+        assert!(!glob("src?main.c", "src/main.c"));
+    }
+}
@@ -0,0 +1,431 @@
+use crate::config::{BuildConfig, Config};
+use crate::project::{Project, ProjectKind};
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// Arguments which are followed by a value that only concerns a single compilation
+const ARGS_WITH_VALUE: &[&str] = &["-o", "-MF", "-MT", "-MQ"];
+
+// Arguments which only concern a single compilation
+const IGNORED_ARGS: &[&str] = &["-c", "-MD", "-MMD", "-MP"];
+
+// Options which take their value as the next argument, they are compared together with it
+const OPTIONS_WITH_VALUE: &[&str] = &[
+    "-x",
+    "-U",
+    "-include",
+    "-imacros",
+    "-isystem",
+    "-iquote",
+    "-idirafter",
+    "-iprefix",
+    "-iwithprefix",
+    "-iwithprefixbefore",
+    "-isysroot",
+    "--sysroot",
+    "-target",
+    "-arch",
+    "-Xclang",
+    "-Xpreprocessor",
+    "-Xassembler",
+    "-Xlinker",
+    "-Xarch_host",
+    "-Xarch_device",
+];
+
+#[derive(Debug, Deserialize)]
+struct Entry {
+    directory: PathBuf,
+    file: PathBuf,
+    command: Option<String>,
+    arguments: Option<Vec<String>>,
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupBy {
+    Directory,
+    Output,
+}
+
+impl GroupBy {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "output" => GroupBy::Output,
+            _ => GroupBy::Directory,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Group {
+    sources: Vec<PathBuf>,
+    include: Vec<String>,
+    defines: Vec<String>,
+    // The options of every entry, an option and its value are kept together
+    options: Vec<Vec<Vec<String>>>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Arguments {
+    include: Vec<String>,
+    defines: Vec<String>,
+    options: Vec<Vec<String>>,
+}
+
+fn split_command(command: &str) -> Vec<String> {
+    // Split a shell command line on whitespace, respecting quotes and backslashes
+    let mut args = vec![];
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+                in_arg = true;
+            }
+            (Some(_), c) => current.push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+
+    if in_arg {
+        args.push(current);
+    }
+
+    args
+}
+
+fn parse_arguments(args: &[String], directory: &Path, file: &Path, root: &Path) -> Arguments {
+    let mut arguments = Arguments::default();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if ARGS_WITH_VALUE.contains(&arg.as_str()) {
+            iter.next();
+        } else if IGNORED_ARGS.contains(&arg.as_str())
+            || directory.join(arg) == directory.join(file)
+        {
+            continue;
+        } else if OPTIONS_WITH_VALUE.contains(&arg.as_str()) {
+            let mut option = vec![arg.clone()];
+            option.extend(iter.next().cloned());
+            arguments.options.push(option);
+        } else if let Some(include) = arg.strip_prefix("-I") {
+            // Include paths can be written as `-Ipath` or `-I path`
+            let include = if include.is_empty() {
+                iter.next().cloned().unwrap_or_default()
+            } else {
+                include.to_string()
+            };
+            let include = relative_to(&directory.join(include), root);
+            arguments
+                .include
+                .push(include.to_str().unwrap().to_string());
+        } else if let Some(define) = arg.strip_prefix("-D") {
+            let define = if define.is_empty() {
+                iter.next().cloned().unwrap_or_default()
+            } else {
+                define.to_string()
+            };
+            arguments.defines.push(define);
+        } else {
+            arguments.options.push(vec![arg.clone()]);
+        }
+    }
+
+    arguments
+}
+
+fn common_options(all_options: &[&Vec<Vec<String>>]) -> Vec<Vec<String>> {
+    // Options which every single entry shares become the global compiler options
+    match all_options.first() {
+        Some(first) => first
+            .iter()
+            .filter(|x| all_options.iter().all(|options| options.contains(x)))
+            .cloned()
+            .collect(),
+        None => vec![],
+    }
+}
+
+fn relative_to(path: &Path, root: &Path) -> PathBuf {
+    path.strip_prefix(root)
+        .map(|x| x.to_path_buf())
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn project_name(path: &Path, taken: &[String]) -> String {
+    // Use the last directory component as the name, falling back to a generic one
+    let base: String = path
+        .file_name()
+        .and_then(|x| x.to_str())
+        .unwrap_or("project")
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    let base = if base.is_empty() {
+        String::from("project")
+    } else {
+        base
+    };
+
+    let mut name = base.clone();
+    let mut index = 2;
+    while taken.contains(&name) {
+        name = format!("{}_{}", base, index);
+        index += 1;
+    }
+
+    name
+}
+
+fn has_main(sources: &[PathBuf]) -> bool {
+    // Guess whether the project is an executable by looking for a main function
+    sources.iter().any(|source| {
+        std::fs::read_to_string(source)
+            .map(|x| x.contains("main("))
+            .unwrap_or(false)
+    })
+}
+
+pub struct Import {
+    pub config: BuildConfig,
+    pub warnings: Vec<String>,
+}
+
+pub fn import(database: &Path, group_by: GroupBy) -> Result<Import, std::io::Error> {
+    let entries: Vec<Entry> = serde_json::from_str(&std::fs::read_to_string(database)?)?;
+    let root = std::env::current_dir()?;
+
+    let mut warnings = vec![];
+    let mut compiler = None;
+    let mut groups: BTreeMap<PathBuf, Group> = BTreeMap::new();
+
+    for entry in entries {
+        let args = match (&entry.arguments, &entry.command) {
+            (Some(arguments), _) => arguments.clone(),
+            (None, Some(command)) => split_command(command),
+            (None, None) => {
+                warnings.push(format!(
+                    "entry for '{}' has no command, skipping it",
+                    entry.file.display()
+                ));
+                continue;
+            }
+        };
+
+        if args.is_empty() {
+            continue;
+        }
+
+        // The first argument is the compiler itself
+        if compiler.is_none() {
+            compiler = Some(args[0].clone());
+        }
+
+        let file = relative_to(&entry.directory.join(&entry.file), &root);
+
+        // Find the group this entry belongs to
+        let key = match (group_by, &entry.output) {
+            (GroupBy::Output, Some(output)) => relative_to(&entry.directory.join(output), &root)
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .to_path_buf(),
+            _ => file.parent().unwrap_or_else(|| Path::new("")).to_path_buf(),
+        };
+        let group = groups.entry(key).or_default();
+
+        let arguments = parse_arguments(&args[1..], &entry.directory, &entry.file, &root);
+        for include in arguments.include {
+            if !group.include.contains(&include) {
+                group.include.push(include);
+            }
+        }
+        for define in arguments.defines {
+            if !group.defines.contains(&define) {
+                group.defines.push(define);
+            }
+        }
+
+        group.sources.push(file);
+        group.options.push(arguments.options);
+    }
+
+    let all_options: Vec<&Vec<Vec<String>>> = groups.values().flat_map(|x| &x.options).collect();
+    let common_options = common_options(&all_options);
+
+    let mut projects: Vec<Project> = vec![];
+    for (path, group) in groups {
+        let names: Vec<_> = projects.iter().map(|x| x.name.clone()).collect();
+        let name = project_name(&path, &names);
+
+        // Buldr has no per project compiler options, so let the user know about them
+        for options in &group.options {
+            for option in options.iter().filter(|x| !common_options.contains(x)) {
+                let warning = format!(
+                    "option '{}' of project '{}' can't be imported",
+                    option.join(" "),
+                    name
+                );
+                if !warnings.contains(&warning) {
+                    warnings.push(warning);
+                }
+            }
+        }
+
+        let kind = if has_main(&group.sources) {
+            ProjectKind::Executable
+        } else {
+            ProjectKind::Library
+        };
+
+        projects.push(Project {
+            name,
+            kind,
+            src: group.sources,
+            include: Some(group.include).filter(|x| !x.is_empty()),
+            defines: Some(group.defines).filter(|x| !x.is_empty()),
+            ..Default::default()
+        });
+    }
+
+    // Make the first executable the default project
+    if let Some(project) = projects
+        .iter_mut()
+        .find(|x| matches!(x.kind, ProjectKind::Executable))
+    {
+        project.default = Some(true);
+    }
+
+    let compiler = compiler.unwrap_or_else(|| String::from("clang"));
+    let compiler = Path::new(&compiler)
+        .file_name()
+        .and_then(|x| x.to_str())
+        .unwrap_or(&compiler)
+        .to_string();
+
+    Ok(Import {
+        config: BuildConfig {
+            config: Config {
+                compiler: Some(compiler.clone()),
+                compiler_opts: Some(common_options.concat()),
+                linker: Some(compiler),
+                linker_opts: Some(vec![]),
+                packer: Some(String::from("ar")),
+                packer_opts: Some(vec![]),
+                bin: String::from("bin/"),
+                obj: String::from("obj/"),
+                ..Default::default()
+            },
+            projects,
+            actions: vec![],
+            targets: BTreeMap::new(),
+        },
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn split_command_on_whitespace() {
+        assert_eq!(
+            split_command("  cc -c   main.c\t-o main.o "),
+            args(&["cc", "-c", "main.c", "-o", "main.o"])
+        );
+    }
+
+    #[test]
+    fn split_command_with_quotes_and_escapes() {
+        assert_eq!(
+            split_command(r#"cc "-DNAME=\"a b\"" '-DPATH=c d' -DX=a\ b"#),
+            args(&["cc", "-DNAME=\"a b\"", "-DPATH=c d", "-DX=a b"])
+        );
+        assert_eq!(split_command(r#"cc '' "a\\b""#), args(&["cc", "", "a\\b"]));
+    }
+
+    #[test]
+    fn options_keep_their_value() {
+        let arguments = parse_arguments(
+            &args(&[
+                "-isystem",
+                "/opt/x",
+                "-O2",
+                "-Xclang",
+                "-DNOT_A_DEFINE",
+                "-x",
+                "c++",
+                "-c",
+                "main.c",
+                "-o",
+                "main.o",
+            ]),
+            Path::new("/src"),
+            Path::new("main.c"),
+            Path::new("/src"),
+        );
+
+        assert_eq!(
+            arguments.options,
+            vec![
+                args(&["-isystem", "/opt/x"]),
+                args(&["-O2"]),
+                args(&["-Xclang", "-DNOT_A_DEFINE"]),
+                args(&["-x", "c++"]),
+            ]
+        );
+        assert!(arguments.defines.is_empty());
+    }
+
+    #[test]
+    fn include_and_define_values() {
+        let arguments = parse_arguments(
+            &args(&["-I", "include", "-Ilib", "-D", "A=1", "-DB", "main.c"]),
+            Path::new("/src"),
+            Path::new("main.c"),
+            Path::new("/src"),
+        );
+
+        assert_eq!(arguments.include, args(&["include", "lib"]));
+        assert_eq!(arguments.defines, args(&["A=1", "B"]));
+        assert!(arguments.options.is_empty());
+    }
+
+    #[test]
+    fn common_options_compare_values() {
+        let first = vec![args(&["-isystem", "/opt/x"]), args(&["-O2"])];
+        let second = vec![args(&["-O2"]), args(&["-isystem", "/opt/y"])];
+        let third = vec![args(&["-isystem", "/opt/x"]), args(&["-O2"])];
+
+        assert_eq!(common_options(&[&first, &second]), vec![args(&["-O2"])]);
+        assert_eq!(common_options(&[&first, &third]), first);
+        assert!(common_options(&[]).is_empty());
+    }
+}
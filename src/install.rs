@@ -0,0 +1,191 @@
+use crate::config::Config;
+use crate::get_all_dependencies;
+use crate::pkg_config::{self, PcLocation};
+use crate::project::{Project, ProjectKind};
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+
+pub const MANIFEST_PATH: &str = "install_manifest.txt";
+
+pub fn get_destination(prefix: &str) -> PathBuf {
+    // DESTDIR is prepended to the prefix, which is how packagers stage an install
+    match std::env::var_os("DESTDIR") {
+        Some(destdir) if !destdir.is_empty() => Path::new(&destdir).join(
+            Path::new(prefix)
+                .strip_prefix("/")
+                .unwrap_or(Path::new(prefix)),
+        ),
+        _ => PathBuf::from(prefix),
+    }
+}
+
+fn copy_file(from: &Path, to: &Path, installed: &mut Vec<PathBuf>) -> Result<(), std::io::Error> {
+    std::fs::create_dir_all(to.parent().unwrap())?;
+    std::fs::copy(from, to)?;
+
+    println!("Installing {}", to.display());
+    installed.push(to.to_path_buf());
+
+    Ok(())
+}
+
+pub fn normalize(path: &Path) -> PathBuf {
+    // Paths can be given as ./src or src, both should compare equal
+    path.components()
+        .filter(|x| !matches!(x, Component::CurDir))
+        .collect()
+}
+
+pub fn get_header_path(project: &Project, header: &Path) -> PathBuf {
+    // A header is included relative to the project's include directory, like `mylib/api.h`
+    let header = normalize(header);
+
+    project
+        .include
+        .iter()
+        .flatten()
+        .filter_map(|x| header.strip_prefix(normalize(Path::new(x))).ok())
+        .min_by_key(|x| x.components().count())
+        .filter(|x| x.file_name().is_some())
+        .unwrap_or_else(|| Path::new(header.file_name().unwrap()))
+        .to_path_buf()
+}
+
+fn install_headers(
+    project: &Project,
+    destination: &Path,
+    installed: &mut Vec<PathBuf>,
+) -> Result<(), std::io::Error> {
+    let include_dir = destination.join("include");
+
+    for header in project.public_headers.iter().flatten() {
+        if header.is_dir() {
+            // Directories keep their layout below the include directory
+            for entry in WalkDir::new(header).into_iter().filter_map(|e| e.ok()) {
+                if entry.file_type().is_file() {
+                    let relative = entry.path().strip_prefix(header).unwrap();
+                    copy_file(entry.path(), &include_dir.join(relative), installed)?;
+                }
+            }
+        } else {
+            copy_file(
+                header,
+                &include_dir.join(get_header_path(project, header)),
+                installed,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn install_pc_file(
+    project: &Project,
+    all_projects: &[Project],
+    prefix: &str,
+    destination: &Path,
+    installed: &mut Vec<PathBuf>,
+) -> Result<(), std::io::Error> {
+    // The installed file refers to the prefix, DESTDIR is only used for staging
+    let location = PcLocation {
+        prefix: prefix.to_string(),
+        libdir: String::from("${prefix}/lib"),
+        includedir: String::from("${prefix}/include"),
+        include_dirs: vec![String::from("${includedir}")],
+    };
+
+    let dependencies = get_all_dependencies(all_projects, project);
+    let contents =
+        pkg_config::generate_pc(project, &dependencies[..dependencies.len() - 1], &location);
+
+    let path = destination
+        .join("lib")
+        .join("pkgconfig")
+        .join(project.get_pc_file_name());
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(&path, contents)?;
+
+    println!("Installing {}", path.display());
+    installed.push(path);
+
+    Ok(())
+}
+
+pub fn install(
+    projects: &[&Project],
+    all_projects: &[Project],
+    config: &Config,
+    prefix: &str,
+    destination: &Path,
+) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut installed = vec![];
+
+    for project in projects {
+        let artifact = project.get_artifact(config);
+        let file_name = artifact.file_name().unwrap();
+
+        match project.kind {
+            ProjectKind::Executable => {
+                copy_file(
+                    &artifact,
+                    &destination.join("bin").join(file_name),
+                    &mut installed,
+                )?;
+            }
+            ProjectKind::Library => {
+                copy_file(
+                    &artifact,
+                    &destination.join("lib").join(file_name),
+                    &mut installed,
+                )?;
+                install_headers(project, destination, &mut installed)?;
+                install_pc_file(project, all_projects, prefix, destination, &mut installed)?;
+            }
+            // Tests are never installed
+            ProjectKind::Test => {}
+        }
+    }
+
+    Ok(installed)
+}
+
+pub fn write_manifest(installed: &[PathBuf]) -> Result<(), std::io::Error> {
+    // Merge with an earlier manifest so separate installs can all be uninstalled
+    let mut files = read_manifest().unwrap_or_default();
+    for file in installed {
+        if !files.contains(file) {
+            files.push(file.clone());
+        }
+    }
+
+    let contents: Vec<_> = files.iter().map(|x| x.to_str().unwrap()).collect();
+    std::fs::write(MANIFEST_PATH, contents.join("\n") + "\n")
+}
+
+fn read_manifest() -> Result<Vec<PathBuf>, std::io::Error> {
+    Ok(std::fs::read_to_string(MANIFEST_PATH)?
+        .lines()
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+pub fn uninstall() -> Result<(), std::io::Error> {
+    let files = match read_manifest() {
+        Ok(files) => files,
+        Err(_) => {
+            eprintln!("No {} found, nothing to uninstall", MANIFEST_PATH);
+            return Ok(());
+        }
+    };
+
+    // Only remove exactly the files that were installed
+    for file in files {
+        if file.exists() {
+            println!("Removing {}", file.display());
+            std::fs::remove_file(&file)?;
+        }
+    }
+
+    std::fs::remove_file(MANIFEST_PATH)
+}
@@ -1,21 +1,46 @@
+use crate::compile_command::CompileCommand;
 use crate::config::BuildConfig;
 use crate::config::Config;
-use crate::project::Project;
+use crate::create::CreateOptions;
+use crate::message::{MessageFormat, Reporter};
+use crate::project::{Project, ProjectKind};
 use cache::Cache;
 use clap::ArgMatches;
 use clap::{App, Arg, SubCommand};
-use std::fs::File;
+use object_cache::ObjectCache;
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::path::PathBuf;
-use std::process::Command;
 use std::process::Stdio;
+use std::time::Duration;
+use termion::color;
 
+mod actions;
 mod cache;
+mod cmake;
 mod compile_command;
 mod config;
+mod create;
+mod glob;
+mod import;
+mod install;
+mod message;
+mod ninja;
+mod object_cache;
+mod options;
+mod pkg_config;
 mod project;
+mod remote_cache;
+mod test_runner;
+mod timings;
+mod toolchain;
+mod watch;
 
+const DEFAULT_BUILD_FILE: &str = "build.toml";
 const COMPILE_COMMANDS_PATH: &str = "compile_commands.json";
+const NINJA_PATH: &str = "build.ninja";
+const CMAKE_PATH: &str = "CMakeLists.txt";
+const DEFAULT_TEST_TIMEOUT: &str = "60";
 
 fn create_directories(config: &BuildConfig) -> Result<(), std::io::Error> {
     // Create the bin directory
@@ -48,11 +73,77 @@ fn get_dependencies<'a>(projects: &'a [Project], project: &Project) -> Vec<&'a P
     }
 }
 
+fn get_all_dependencies<'a>(projects: &'a [Project], project: &'a Project) -> Vec<&'a Project> {
+    let mut result: Vec<&'a Project> = vec![];
+
+    // Add the dependencies before the project that depends on them
+    for dependency in get_dependencies(projects, project) {
+        for x in get_all_dependencies(projects, dependency) {
+            if !result.iter().any(|y| y.name == x.name) {
+                result.push(x);
+            }
+        }
+    }
+
+    result.push(project);
+    result
+}
+
+fn check_requirements(all_projects: &[Project], projects: &[&Project]) {
+    // Make sure all system dependencies are available before compiling anything
+    for project in projects {
+        for x in get_all_dependencies(all_projects, project) {
+            if let Err(e) = x.check_requirements() {
+                eprintln!("{}: {}", x.name, e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn write_pc_files(config: &BuildConfig, projects: &[&Project]) -> Result<(), std::io::Error> {
+    let root = std::env::current_dir()?;
+
+    for project in projects {
+        if !matches!(project.kind, ProjectKind::Library) {
+            continue;
+        }
+
+        // The headers of the dependencies are spread around the source tree as well
+        let dependencies = get_all_dependencies(&config.projects, project);
+        let mut include_dirs = vec![];
+        for x in dependencies.iter().rev() {
+            for dir in x.get_public_include_dirs() {
+                let dir = root.join(dir).to_str().unwrap().to_string();
+                if !include_dirs.contains(&dir) {
+                    include_dirs.push(dir);
+                }
+            }
+        }
+
+        // The file next to the archive points into the source tree
+        let location = pkg_config::PcLocation {
+            prefix: root.to_str().unwrap().to_string(),
+            libdir: root.join(&config.config.bin).to_str().unwrap().to_string(),
+            includedir: String::from("${prefix}"),
+            include_dirs,
+        };
+
+        std::fs::write(
+            Path::new(&config.config.bin).join(project.get_pc_file_name()),
+            pkg_config::generate_pc(project, &dependencies[..dependencies.len() - 1], &location),
+        )?;
+    }
+
+    Ok(())
+}
+
 fn build_project_with_dependencies(
     project: &Project,
     all_projects: &[Project],
     config: &Config,
     cache: &mut Cache,
+    reporter: &mut Reporter,
 ) -> Result<bool, std::io::Error> {
     // Get all the dependencies
     let dependencies = get_dependencies(all_projects, project);
@@ -61,42 +152,104 @@ fn build_project_with_dependencies(
 
     // Compile them in the correct order
     for dependency in dependencies {
-        needs_rebuild |= build_project_with_dependencies(dependency, all_projects, config, cache)?;
+        needs_rebuild |=
+            build_project_with_dependencies(dependency, all_projects, config, cache, reporter)?;
     }
 
-    // Finally build the resulting project
-    project.build(needs_rebuild, cache, config)
+    // Finally build the resulting project, it links the system libraries of its dependencies too
+    project.build(
+        needs_rebuild,
+        &get_all_dependencies(all_projects, project),
+        cache,
+        config,
+        reporter,
+    )
 }
 
-fn load_config(build_file: &str) -> Result<BuildConfig, std::io::Error> {
-    Ok(toml::from_str::<BuildConfig>(&std::fs::read_to_string(
-        build_file,
-    )?)?)
+fn load_config(build_file: &str, target: Option<&str>) -> Result<BuildConfig, std::io::Error> {
+    let mut config = toml::from_str::<BuildConfig>(&std::fs::read_to_string(build_file)?)?;
+
+    if let Some(triple) = target {
+        if !config.apply_target(triple) {
+            eprintln!("No target found with name '{}'", triple);
+            std::process::exit(1);
+        }
+    }
+
+    // Fill in the tools the build file leaves out
+    let cpp = config.uses_cpp();
+    config.config.detect_toolchain(cpp);
+
+    Ok(config)
 }
 
-fn create(build_file_path: &Path) -> Result<(), std::io::Error> {
+fn create(build_file_path: &Path, matches: &ArgMatches) -> Result<(), std::io::Error> {
     // If there is already a build.toml file, don't overwrite it!
     if build_file_path.exists() {
         println!("build.toml already exists");
         return Ok(());
     }
 
-    // Create a new build.toml file
-    File::create(build_file_path)?;
+    let options = CreateOptions {
+        name: matches.value_of("name").map(|x| x.to_string()),
+        kind: match matches.value_of("kind") {
+            Some("library") => ProjectKind::Library,
+            _ => ProjectKind::Executable,
+        },
+    };
 
-    // Write the template file to build.toml
-    let template = include_str!("template.toml");
-    std::fs::write(build_file_path, template)
+    // Look at the directory the build file will be placed in
+    let dir = match build_file_path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    let config = create::create(dir, options)?;
+
+    // Write the generated config to build.toml
+    std::fs::write(build_file_path, toml::to_string(&config).unwrap())
+}
+
+fn import(build_file_path: &Path, matches: &ArgMatches) -> Result<(), std::io::Error> {
+    // If there is already a build.toml file, don't overwrite it!
+    if build_file_path.exists() {
+        println!("build.toml already exists");
+        return Ok(());
+    }
+
+    let matches = match matches.subcommand_matches("compile-commands") {
+        Some(matches) => matches,
+        None => {
+            eprintln!("No import format specified");
+            return Ok(());
+        }
+    };
+
+    // Convert the compilation database to a build config
+    let import = import::import(
+        Path::new(matches.value_of("file").unwrap()),
+        import::GroupBy::from_name(matches.value_of("group-by").unwrap_or("directory")),
+    )?;
+
+    // Let the user know which parts of the database couldn't be imported
+    for warning in &import.warnings {
+        eprintln!("warning: {}", warning);
+    }
+
+    // Make sure the result can be loaded again before writing it
+    let contents = toml::to_string(&import.config).unwrap();
+    toml::from_str::<BuildConfig>(&contents)?;
+
+    std::fs::write(build_file_path, contents)
 }
 
-fn clean(build_file: &str) -> Result<(), std::io::Error> {
+fn clean(build_file: &str, matches: &ArgMatches) -> Result<(), std::io::Error> {
     // If the build file exists, clear the cache
     if PathBuf::from(build_file).exists() {
         Cache::new(build_file)?.clean();
     }
 
     // Load the config
-    let config = load_config(build_file)?;
+    let config = load_config(build_file, matches.value_of("target"))?;
 
     // Remove the bin dir if it exists
     if PathBuf::from(&config.config.bin).exists() {
@@ -111,120 +264,873 @@ fn clean(build_file: &str) -> Result<(), std::io::Error> {
     Ok(())
 }
 
-fn compile_commands(build_file: &str) -> Result<(), std::io::Error> {
-    // Load the config
-    let config = load_config(build_file)?;
-
+fn get_compile_commands(
+    config: &BuildConfig,
+    projects: &[&Project],
+) -> Result<Vec<CompileCommand>, std::io::Error> {
     // List to store all compile commands of all projects
     let mut all_compile_commands = vec![];
 
-    for project in config.projects {
+    for project in projects {
         // Get the compile commands of each project and add it to the list
-        all_compile_commands.append(
-            &mut project.get_compile_commands(&project.get_source_files()[..], &config.config),
-        );
+        let mut compile_commands = project
+            .get_compile_commands(
+                &project.get_all_source_files(&config.config)[..],
+                &config.config,
+            )
+            .map_err(|e| std::io::Error::other(format!("{}: {}", project.name, e)))?;
+        all_compile_commands.append(&mut compile_commands);
     }
 
-    // Write the result to compile_commands.json
+    Ok(all_compile_commands)
+}
+
+fn write_compile_commands(
+    config: &BuildConfig,
+    projects: &[&Project],
+    path: &Path,
+) -> Result<(), std::io::Error> {
+    let all_compile_commands = get_compile_commands(config, projects)?;
+
+    // Write the result to the compilation database
     std::fs::write(
-        Path::new(COMPILE_COMMANDS_PATH),
-        serde_json::to_string(&all_compile_commands).unwrap(),
+        path,
+        serde_json::to_string_pretty(&all_compile_commands).unwrap(),
     )
 }
 
-fn build(build_file: &str, matches: &ArgMatches) -> Result<Option<PathBuf>, std::io::Error> {
-    // Make sure the build file exists
-    if !PathBuf::from(build_file).exists() {
-        eprintln!("No build.toml file found!");
-        return Ok(None);
+fn update_compile_commands(
+    config: &BuildConfig,
+    projects: &[&Project],
+    path: &Path,
+) -> Result<(), std::io::Error> {
+    let compile_commands = get_compile_commands(config, projects)?;
+
+    // Only the given projects are replaced, the entries of all other projects are kept
+    let existing: Vec<serde_json::Value> = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|x| serde_json::from_str(&x).ok())
+        .unwrap_or_default();
+    let mut entries: Vec<_> = existing
+        .into_iter()
+        .filter(|entry| {
+            let output = Path::new(entry["output"].as_str().unwrap_or(""));
+            !projects
+                .iter()
+                .any(|x| output.starts_with(Path::new(&config.config.obj).join(&x.name)))
+        })
+        .collect();
+    for command in &compile_commands {
+        entries.push(serde_json::to_value(command)?);
     }
 
+    std::fs::write(path, serde_json::to_string_pretty(&entries).unwrap())
+}
+
+fn compile_commands(build_file: &str, matches: &ArgMatches) -> Result<(), std::io::Error> {
     // Load the config
-    let config = load_config(build_file)?;
+    let config = load_config(build_file, matches.value_of("target"))?;
 
-    // Create the bin and obj directories
-    create_directories(&config)?;
+    // Either limit the database to a single project and its dependencies or include everything
+    let projects = match matches.value_of("project") {
+        Some(name) => match config.projects.iter().find(|x| x.name == name) {
+            Some(project) => get_all_dependencies(&config.projects, project),
+            None => {
+                eprintln!("No project found with name '{}'", name);
+                return Ok(());
+            }
+        },
+        None => config.projects.iter().collect(),
+    };
 
-    // Load or create the cache
-    let mut cache = Cache::new(build_file)?;
+    let path = matches.value_of("output").unwrap_or(COMPILE_COMMANDS_PATH);
+    if let Err(e) = write_compile_commands(&config, &projects, Path::new(path)) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 
-    // Make sure there are some projects defined
-    if config.projects.is_empty() {
-        eprintln!("No projects defined");
-        return Ok(None);
+    Ok(())
+}
+
+fn ninja(build_file: &str, matches: &ArgMatches) -> Result<(), std::io::Error> {
+    // Load the config
+    let config = load_config(build_file, matches.value_of("target"))?;
+
+    // Generated sources only get an edge when they exist, so generate them like a build would
+    let mut reporter = Reporter::new(MessageFormat::from_name(
+        matches.value_of("message-format").unwrap_or("human"),
+    ));
+    for project in &config.projects {
+        project.run_generators(&config.config, &mut reporter);
     }
 
-    // Find which project to compile
-    let project = match matches.value_of("project") {
+    let export = match ninja::generate(&config) {
+        Ok(export) => export,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Let the user know which parts of the config couldn't be translated
+    for warning in &export.warnings {
+        eprintln!("warning: {}", warning);
+    }
+
+    // Write the generated ninja file to build.ninja
+    std::fs::write(Path::new(NINJA_PATH), export.contents)
+}
+
+fn export(build_file: &str, matches: &ArgMatches) -> Result<(), std::io::Error> {
+    // Load the config
+    let config = load_config(build_file, matches.value_of("target"))?;
+
+    match matches.subcommand_name() {
+        Some("cmake") => {
+            let export = cmake::generate(&config);
+
+            // Let the user know which parts of the config couldn't be translated
+            for warning in &export.warnings {
+                eprintln!("warning: {}", warning);
+            }
+
+            // Write the result to CMakeLists.txt
+            std::fs::write(Path::new(CMAKE_PATH), export.contents)
+        }
+        _ => {
+            eprintln!("No export format specified");
+            Ok(())
+        }
+    }
+}
+
+fn cache(build_file: &str, matches: &ArgMatches) -> Result<(), std::io::Error> {
+    // The settings of the build file are used when there is one
+    let config = if PathBuf::from(build_file).exists() {
+        Some(load_config(build_file, matches.value_of("target"))?)
+    } else {
+        None
+    };
+    let object_cache = ObjectCache::open(config.as_ref().map(|x| &x.config));
+
+    match matches.subcommand_name() {
+        Some("stats") => {
+            let stats = object_cache.stats();
+            let lookups = stats.hits + stats.misses;
+            let megabytes = |x: u64| x as f64 / (1024.0 * 1024.0);
+
+            println!("cache directory: {}", stats.dir.display());
+            println!("objects:         {}", stats.entries);
+            println!(
+                "size:            {:.1} MB of {:.0} MB",
+                megabytes(stats.size),
+                megabytes(stats.max_size)
+            );
+            println!(
+                "hits:            {} of {} ({:.0}%)",
+                stats.hits,
+                lookups,
+                if lookups == 0 {
+                    0.0
+                } else {
+                    stats.hits as f64 * 100.0 / lookups as f64
+                }
+            );
+            println!("  from remote:   {}", stats.remote_hits);
+            println!("misses:          {}", stats.misses);
+            Ok(())
+        }
+        Some("serve") => {
+            let matches = matches.subcommand_matches("serve").unwrap();
+            let dir = match matches.value_of("dir") {
+                Some(dir) => PathBuf::from(dir),
+                None => object_cache.stats().dir,
+            };
+
+            remote_cache::serve(matches.value_of("address").unwrap(), &dir)
+        }
+        _ => {
+            eprintln!("No cache command specified");
+            Ok(())
+        }
+    }
+}
+
+fn doctor(build_file: &str, matches: &ArgMatches) -> Result<(), std::io::Error> {
+    // Without a build file, check the tools buldr would pick on its own
+    let mut config = if PathBuf::from(build_file).exists() {
+        toml::from_str::<BuildConfig>(&std::fs::read_to_string(build_file)?)?
+    } else {
+        BuildConfig {
+            config: Config::default(),
+            projects: vec![],
+            actions: vec![],
+            targets: BTreeMap::new(),
+        }
+    };
+
+    let configured = [
+        config.config.compiler.is_some(),
+        config.config.linker.is_some(),
+        config.config.packer.is_some(),
+    ];
+    let target = matches.value_of("target");
+    let configured = match target.and_then(|x| config.targets.get(x)) {
+        Some(tools) => [
+            configured[0] || tools.compiler.is_some(),
+            configured[1] || tools.linker.is_some() || tools.compiler.is_some(),
+            configured[2] || tools.packer.is_some(),
+        ],
+        None => configured,
+    };
+
+    if let Some(triple) = target {
+        if !config.apply_target(triple) {
+            eprintln!("No target found with name '{}'", triple);
+            std::process::exit(1);
+        }
+    }
+
+    let cpp = config.uses_cpp();
+    config.config.detect_toolchain(cpp);
+
+    let tools = [
+        ("compiler", config.config.compiler()),
+        ("linker", config.config.linker()),
+        ("packer", config.config.packer()),
+    ];
+    for ((role, program), configured) in tools.iter().zip(configured.iter()) {
+        let path = match toolchain::find_program(program) {
+            Some(path) => path.display().to_string(),
+            None => String::from("not found"),
+        };
+        println!(
+            "{:9} {} ({}, {})",
+            role,
+            program,
+            path,
+            if *configured {
+                "configured"
+            } else {
+                "detected"
+            }
+        );
+
+        let version = toolchain::get_version(program);
+        if let Some(line) = version.lines().next().filter(|_| version != *program) {
+            println!("          {}", line);
+        }
+    }
+    println!();
+
+    let checks = toolchain::check(&config.config, cpp, target.is_none());
+    for check in &checks {
+        match &check.result {
+            Ok(_) => println!(
+                "{:9} {}ok{}",
+                check.name,
+                color::Fg(color::Green),
+                color::Fg(color::Reset)
+            ),
+            Err(message) => {
+                println!(
+                    "{:9} {}failed{}",
+                    check.name,
+                    color::Fg(color::Red),
+                    color::Fg(color::Reset)
+                );
+                for line in message.lines() {
+                    println!("          {}", line);
+                }
+            }
+        }
+    }
+
+    if checks.iter().any(|x| x.result.is_err()) {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn find_project<'a>(config: &'a BuildConfig, name: Option<&str>) -> Option<&'a Project> {
+    match name {
         Some(name) => match config.projects.iter().find(|x| x.name == name) {
-            Some(project) => project,
+            Some(project) => Some(project),
             None => {
                 eprintln!("No project found with name '{}'", name);
-                return Ok(None);
+                None
             }
         },
         None => match config.projects.iter().find(|x| x.default == Some(true)) {
-            Some(project) => project,
+            Some(project) => Some(project),
             None => {
                 eprintln!("No default project");
-                return Ok(None);
+                None
             }
         },
+    }
+}
+
+fn build(build_file: &str, matches: &ArgMatches) -> Result<Option<PathBuf>, std::io::Error> {
+    let config = match load_build_file(build_file, matches)? {
+        Some(config) => config,
+        None => return Ok(None),
+    };
+
+    // Make sure there are some projects defined
+    if config.projects.is_empty() {
+        eprintln!("No projects defined");
+        return Ok(None);
+    }
+
+    // Find which project to compile
+    let project = match find_project(&config, matches.value_of("project")) {
+        Some(project) => project,
+        None => return Ok(None),
     };
 
     // Build that project and its dependencies
-    build_project_with_dependencies(project, &config.projects, &config.config, &mut cache)?;
-    let output = Path::new(&config.config.bin).join(&project.name);
+    let reporter = build_projects(build_file, &config, &[project], matches)?;
+
+    // Let other build systems find the libraries that were built
+    write_pc_files(&config, &get_all_dependencies(&config.projects, project))?;
+
+    // Report how long each compile and link step took, away from the JSON messages on stdout
+    if matches.is_present("timings") {
+        if reporter.is_human() {
+            reporter.timings.write_report(&mut std::io::stdout())?;
+        } else {
+            reporter.timings.write_report(&mut std::io::stderr())?;
+        }
+    }
+
+    // Export the timings so they can be inspected in a trace viewer
+    if let Some(trace_file) = matches.value_of("trace") {
+        reporter.timings.write_trace(Path::new(trace_file))?;
+    }
+
+    // Keep the compilation database of the built projects up to date for editors, it isn't
+    // worth failing a successful build over
+    if let Err(e) = update_compile_commands(
+        &config,
+        &get_all_dependencies(&config.projects, project),
+        Path::new(COMPILE_COMMANDS_PATH),
+    ) {
+        eprintln!("warning: failed to update {}: {}", COMPILE_COMMANDS_PATH, e);
+    }
 
-    Ok(Some(output))
+    Ok(Some(project.get_artifact(&config.config)))
+}
+
+fn load_build_file(
+    build_file: &str,
+    matches: &ArgMatches,
+) -> Result<Option<BuildConfig>, std::io::Error> {
+    // Make sure the build file exists
+    if !PathBuf::from(build_file).exists() {
+        eprintln!("No build.toml file found!");
+        return Ok(None);
+    }
+
+    load_config(build_file, matches.value_of("target")).map(Some)
+}
+
+fn build_projects(
+    build_file: &str,
+    config: &BuildConfig,
+    projects: &[&Project],
+    matches: &ArgMatches,
+) -> Result<Reporter, std::io::Error> {
+    // Create the bin and obj directories
+    create_directories(config)?;
+
+    // Load or create the cache
+    let mut cache = Cache::new(build_file)?;
+
+    check_requirements(&config.projects, projects);
+
+    // Set up the reporter which prints the build events in the requested format
+    let mut reporter = Reporter::new(MessageFormat::from_name(
+        matches.value_of("message-format").unwrap_or("human"),
+    ));
+
+    // Build the projects together with the libraries they depend on
+    for project in projects {
+        build_project_with_dependencies(
+            project,
+            &config.projects,
+            &config.config,
+            &mut cache,
+            &mut reporter,
+        )?;
+    }
+    reporter.finish(true);
+
+    Ok(reporter)
 }
 
 fn run(build_file: &str, matches: &ArgMatches) -> Result<(), std::io::Error> {
-    if let Some(output) = build(build_file, matches)? {
-        Command::new(output)
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .output()
-            .unwrap();
+    // Find the project to run before building anything
+    let config = match load_build_file(build_file, matches)? {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+    let project = match find_project(&config, matches.value_of("project")) {
+        Some(project) => project,
+        None => return Ok(()),
+    };
+
+    // Only executables can be run
+    if let ProjectKind::Library = project.kind {
+        eprintln!("Project '{}' is a library and can't be run", project.name);
+        std::process::exit(1);
+    }
+
+    if build(build_file, matches)?.is_none() {
+        return Ok(());
+    }
+
+    // Arguments on the command line come after the ones from the run settings
+    let mut command = project.get_run_command(&config.config)?;
+    if let Some(args) = matches.values_of("args") {
+        command.args(args);
+    }
+
+    // Hand the terminal over to the program and pass on its exit code
+    let status = command
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()?;
+
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+fn test(build_file: &str, matches: &ArgMatches) -> Result<(), std::io::Error> {
+    let config = match load_build_file(build_file, matches)? {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+
+    // Find all test projects matching the filter
+    let filter = matches.value_of("filter").unwrap_or("");
+    let tests: Vec<_> = config
+        .projects
+        .iter()
+        .filter(|x| matches!(x.kind, ProjectKind::Test) && x.name.contains(filter))
+        .collect();
+
+    if tests.is_empty() {
+        eprintln!("No test projects found");
+        return Ok(());
+    }
+
+    let timeout = match matches
+        .value_of("timeout")
+        .unwrap_or(DEFAULT_TEST_TIMEOUT)
+        .parse::<u64>()
+    {
+        Ok(timeout) => Duration::from_secs(timeout),
+        Err(_) => {
+            eprintln!("Invalid timeout");
+            std::process::exit(1);
+        }
+    };
+
+    // Build every test together with the libraries it depends on
+    build_projects(build_file, &config, &tests, matches)?;
+
+    // Run all the tests one after the other
+    let mut results = vec![];
+    for project in &tests {
+        let result = test_runner::run_test(
+            &project.name,
+            project.get_run_command(&config.config)?,
+            timeout,
+        )?;
+        test_runner::print_result(&result);
+        results.push(result);
+    }
+
+    test_runner::print_summary(&results);
+
+    // Write a report CI systems can understand
+    if let Some(path) = matches.value_of("junit") {
+        test_runner::write_junit(&results, Path::new(path))?;
+    }
+
+    if results
+        .iter()
+        .any(|x| x.status != test_runner::TestStatus::Passed)
+    {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn install(build_file: &str, matches: &ArgMatches) -> Result<(), std::io::Error> {
+    let config = match load_build_file(build_file, matches)? {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+
+    // Install either the given project or everything except the tests
+    let projects: Vec<_> = match matches.value_of("project") {
+        Some(name) => match find_project(&config, Some(name)) {
+            Some(project) => vec![project],
+            None => return Ok(()),
+        },
+        None => config
+            .projects
+            .iter()
+            .filter(|x| !matches!(x.kind, ProjectKind::Test))
+            .collect(),
+    };
+
+    // Make sure everything that will be installed is up to date
+    build_projects(build_file, &config, &projects, matches)?;
+
+    // Copy the artifacts and remember what was installed
+    let prefix = matches.value_of("prefix").unwrap();
+    let destination = install::get_destination(prefix);
+    let installed = install::install(
+        &projects,
+        &config.projects,
+        &config.config,
+        prefix,
+        &destination,
+    )?;
+    install::write_manifest(&installed)
+}
+
+fn code_action(build_file: &str, name: &str, matches: &ArgMatches) -> Result<(), std::io::Error> {
+    // Load the config
+    let config = load_config(build_file, matches.value_of("target"))?;
+
+    // Run the action on a single project or on all of them
+    let projects: Vec<_> = match matches.value_of("project") {
+        Some(project) => match find_project(&config, Some(project)) {
+            Some(project) => vec![project],
+            None => return Ok(()),
+        },
+        None => config.projects.iter().collect(),
+    };
+
+    let jobs = match name {
+        "fmt" => actions::format_jobs(&projects, matches.is_present("check")),
+        _ => match actions::tidy_jobs(&projects, &config.config) {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+    };
+
+    let results = actions::run_parallel(jobs);
+    if !actions::print_report(name, &results) {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn user_action(build_file: &str, matches: &ArgMatches) -> Result<(), std::io::Error> {
+    // Load the config
+    let config = load_config(build_file, matches.value_of("target"))?;
+
+    // Find the action with the given name
+    let name = matches.value_of("action").unwrap();
+    let action = match config.actions.iter().find(|x| x.name == name) {
+        Some(action) => action,
+        None => {
+            eprintln!("No action found with name '{}'", name);
+            std::process::exit(1);
+        }
+    };
+
+    // Run the action on a single project or on all of them
+    let projects: Vec<_> = match matches.value_of("project") {
+        Some(project) => match find_project(&config, Some(project)) {
+            Some(project) => vec![project],
+            None => return Ok(()),
+        },
+        None => config.projects.iter().collect(),
+    };
+
+    let results = actions::run_parallel(actions::custom_jobs(action, &projects));
+    if !actions::print_report(name, &results) {
+        std::process::exit(1);
     }
 
     Ok(())
 }
 
+fn watch(build_file: &str, matches: &ArgMatches) -> Result<(), std::io::Error> {
+    // Make sure the build file exists
+    if !PathBuf::from(build_file).exists() {
+        eprintln!("No build.toml file found!");
+        return Ok(());
+    }
+
+    let project = matches.value_of("project");
+
+    // Rebuild through buldr itself, optionally running or testing the result afterwards
+    let mut build_args = vec![];
+    if build_file != DEFAULT_BUILD_FILE {
+        build_args.push(String::from("--build-file"));
+        build_args.push(build_file.to_string());
+    }
+    if let Some(target) = matches.value_of("target") {
+        build_args.push(String::from("--target"));
+        build_args.push(target.to_string());
+    }
+    if matches.is_present("run") {
+        build_args.push(String::from("run"));
+        build_args.extend(project.map(|x| x.to_string()));
+    } else if matches.is_present("test") {
+        build_args.push(String::from("test"));
+    } else {
+        build_args.extend(project.map(|x| x.to_string()));
+    }
+
+    watch::watch(build_file, project, &build_args)
+}
+
 fn main() -> Result<(), std::io::Error> {
-    let matches = App::new("Buldr")
-        .version("0.0.1")
-        .author("Hector Peeters <hector.peeters@gmail.com>")
-        .arg(Arg::with_name("project").index(1))
-        .arg(
-            Arg::with_name("build-file")
-                .short("b")
-                .long("build-file")
-                .takes_value(true),
-        )
-        .subcommand(SubCommand::with_name("create").about("generate a template build.toml file"))
-        .subcommand(SubCommand::with_name("clean").about("Clean all build files"))
-        .subcommand(
-            SubCommand::with_name("compile_commands").about("Generate compile_commands.json"),
-        )
-        .subcommand(
-            SubCommand::with_name("run").about("Build and run the default compiled executable"),
-        )
-        .get_matches();
+    let matches =
+        App::new("Buldr")
+            .version("0.0.1")
+            .author("Hector Peeters <hector.peeters@gmail.com>")
+            .arg(Arg::with_name("project").index(1))
+            .arg(
+                Arg::with_name("build-file")
+                    .short("b")
+                    .long("build-file")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("target")
+                    .long("target")
+                    .takes_value(true)
+                    .global(true)
+                    .help("Build for this target triple, as set up in a [target.<triple>] section"),
+            )
+            .arg(
+                Arg::with_name("message-format")
+                    .long("message-format")
+                    .takes_value(true)
+                    .possible_values(&["human", "json"])
+                    .global(true),
+            )
+            .arg(
+                Arg::with_name("timings")
+                    .long("timings")
+                    .help("Print the slowest files and projects after building")
+                    .global(true),
+            )
+            .arg(
+                Arg::with_name("trace")
+                    .long("trace")
+                    .takes_value(true)
+                    .help("Write a Chrome trace event file of the build")
+                    .global(true),
+            )
+            .subcommand(
+                SubCommand::with_name("create")
+                    .about("generate a template build.toml file")
+                    .arg(Arg::with_name("name").long("name").takes_value(true))
+                    .arg(
+                        Arg::with_name("kind")
+                            .long("kind")
+                            .takes_value(true)
+                            .possible_values(&["executable", "library"]),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("import")
+                    .about("Generate a build.toml file from another build system")
+                    .subcommand(
+                        SubCommand::with_name("compile-commands")
+                            .about("Import a compile_commands.json file")
+                            .arg(Arg::with_name("file").required(true).index(1))
+                            .arg(
+                                Arg::with_name("group-by")
+                                    .long("group-by")
+                                    .takes_value(true)
+                                    .possible_values(&["directory", "output"]),
+                            ),
+                    ),
+            )
+            .subcommand(SubCommand::with_name("clean").about("Clean all build files"))
+            .subcommand(
+                SubCommand::with_name("compile_commands")
+                    .about("Generate compile_commands.json")
+                    .arg(
+                        Arg::with_name("project")
+                            .short("p")
+                            .long("project")
+                            .takes_value(true)
+                            .help("Only include this project and its dependencies"),
+                    )
+                    .arg(
+                        Arg::with_name("output")
+                            .short("o")
+                            .long("output")
+                            .takes_value(true)
+                            .help("Write the database to this path"),
+                    ),
+            )
+            .subcommand(SubCommand::with_name("ninja").about("Generate a build.ninja file"))
+            .subcommand(
+                SubCommand::with_name("export")
+                    .about("Export the build configuration to another build system")
+                    .subcommand(SubCommand::with_name("cmake").about("Generate a CMakeLists.txt")),
+            )
+            .subcommand(
+                SubCommand::with_name("test")
+                    .about("Build and run all test projects")
+                    .arg(
+                        Arg::with_name("filter")
+                            .index(1)
+                            .help("Only run tests whose name contains this"),
+                    )
+                    .arg(
+                        Arg::with_name("timeout")
+                            .long("timeout")
+                            .takes_value(true)
+                            .help("Seconds after which a test is killed"),
+                    )
+                    .arg(
+                        Arg::with_name("junit")
+                            .long("junit")
+                            .takes_value(true)
+                            .help("Write a JUnit XML report to this path"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("install")
+                    .about("Build and install the projects")
+                    .arg(Arg::with_name("project").index(1))
+                    .arg(
+                        Arg::with_name("prefix")
+                            .long("prefix")
+                            .takes_value(true)
+                            .default_value("/usr/local"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("uninstall").about("Remove everything that was installed"),
+            )
+            .subcommand(
+                SubCommand::with_name("fmt")
+                    .about("Format all source and header files with clang-format")
+                    .arg(Arg::with_name("project").index(1))
+                    .arg(
+                        Arg::with_name("check")
+                            .long("check")
+                            .help("Fail when any file isn't formatted instead of formatting it"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("tidy")
+                    .about("Lint all source files with clang-tidy")
+                    .arg(Arg::with_name("project").index(1)),
+            )
+            .subcommand(
+                SubCommand::with_name("do")
+                    .about("Run a user-defined action from build.toml")
+                    .arg(Arg::with_name("action").required(true).index(1))
+                    .arg(Arg::with_name("project").index(2)),
+            )
+            .subcommand(
+                SubCommand::with_name("watch")
+                    .about("Rebuild whenever a source file or the build file changes")
+                    .arg(Arg::with_name("project").index(1))
+                    .arg(
+                        Arg::with_name("run")
+                            .long("run")
+                            .help("Run the project after every build"),
+                    )
+                    .arg(
+                        Arg::with_name("test")
+                            .long("test")
+                            .conflicts_with("run")
+                            .help("Run the tests after every build"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("doctor")
+                    .about("Check that the toolchain can compile and link a program"),
+            )
+            .subcommand(
+                SubCommand::with_name("cache")
+                    .about("Manage the shared object cache")
+                    .subcommand(SubCommand::with_name("stats").about("Show the size and hit rate"))
+                    .subcommand(
+                        SubCommand::with_name("serve")
+                            .about("Serve a directory as a remote object cache over HTTP")
+                            .arg(
+                                Arg::with_name("address")
+                                    .long("address")
+                                    .takes_value(true)
+                                    .default_value("127.0.0.1:8080"),
+                            )
+                            .arg(Arg::with_name("dir").long("dir").takes_value(true).help(
+                                "Directory to store the objects in, the local cache by default",
+                            )),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("run")
+                    .about("Build and run the default compiled executable")
+                    .arg(Arg::with_name("project").index(1))
+                    .arg(
+                        Arg::with_name("args")
+                            .multiple(true)
+                            .last(true)
+                            .help("Arguments passed to the executable"),
+                    ),
+            )
+            .get_matches();
 
     // Get the path to the build.toml file
-    let build_file = matches.value_of("build-file").unwrap_or("build.toml");
+    let build_file = matches.value_of("build-file").unwrap_or(DEFAULT_BUILD_FILE);
     let build_file_path = PathBuf::from(build_file);
 
-    if matches.is_present("build-file") {
+    if matches.is_present("build-file") && matches.value_of("message-format") != Some("json") {
         println!("Using custom config: {}", build_file);
     }
 
     match matches.subcommand_name() {
-        Some("create") => create(&build_file_path),
-        Some("clean") => clean(build_file),
-        Some("compile_commands") => compile_commands(build_file),
-        Some("run") => run(build_file, &matches),
+        Some("create") => create(
+            &build_file_path,
+            matches.subcommand_matches("create").unwrap(),
+        ),
+        Some("import") => import(
+            &build_file_path,
+            matches.subcommand_matches("import").unwrap(),
+        ),
+        Some("clean") => clean(build_file, matches.subcommand_matches("clean").unwrap()),
+        Some("compile_commands") => compile_commands(
+            build_file,
+            matches.subcommand_matches("compile_commands").unwrap(),
+        ),
+        Some("ninja") => ninja(build_file, matches.subcommand_matches("ninja").unwrap()),
+        Some("export") => export(build_file, matches.subcommand_matches("export").unwrap()),
+        Some("test") => test(build_file, matches.subcommand_matches("test").unwrap()),
+        Some("install") => install(build_file, matches.subcommand_matches("install").unwrap()),
+        Some("uninstall") => install::uninstall(),
+        Some(name @ "fmt") | Some(name @ "tidy") => {
+            code_action(build_file, name, matches.subcommand_matches(name).unwrap())
+        }
+        Some("do") => user_action(build_file, matches.subcommand_matches("do").unwrap()),
+        Some("watch") => watch(build_file, matches.subcommand_matches("watch").unwrap()),
+        Some("doctor") => doctor(build_file, matches.subcommand_matches("doctor").unwrap()),
+        Some("cache") => cache(build_file, matches.subcommand_matches("cache").unwrap()),
+        Some("run") => run(build_file, matches.subcommand_matches("run").unwrap()),
         Some(_) | None => build(build_file, &matches).map(|_| ()),
     }
 }
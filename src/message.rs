@@ -0,0 +1,314 @@
+use crate::timings::{TimingKind, Timings};
+use serde_derive::Serialize;
+use std::time::Instant;
+use termion::color;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageFormat {
+    Human,
+    Json,
+}
+
+impl MessageFormat {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "json" => MessageFormat::Json,
+            _ => MessageFormat::Human,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: u32,
+    pub column: Option<u32>,
+    pub severity: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn parse_line(line: &str) -> Option<Self> {
+        // Diagnostics look like `file:line:column: severity: message`, the column is optional.
+        // The first severity wins, the message itself can contain another one
+        let (index, severity) = ["fatal error", "error", "warning", "note", "remark"]
+            .iter()
+            .filter_map(|severity| {
+                line.find(&format!(": {}: ", severity))
+                    .map(|index| (index, *severity))
+            })
+            .min_by_key(|x| x.0)?;
+
+        let location = &line[..index];
+        let message = &line[index + format!(": {}: ", severity).len()..];
+
+        let mut parts = location.rsplitn(3, ':');
+        let last = parts.next()?.parse::<u32>().ok()?;
+
+        let (file, line, column) = match parts.next()?.parse::<u32>() {
+            Ok(line) => (parts.next()?, line, Some(last)),
+            Err(_) => (location.rsplit_once(':')?.0, last, None),
+        };
+
+        Some(Diagnostic {
+            file: file.to_string(),
+            line,
+            column,
+            severity: severity.to_string(),
+            message: message.to_string(),
+        })
+    }
+
+    pub fn parse(output: &str) -> Vec<Self> {
+        output.lines().filter_map(Self::parse_line).collect()
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+enum Message<'a> {
+    ProjectStarted {
+        project: &'a str,
+    },
+    FileCompiled {
+        project: &'a str,
+        file: &'a str,
+        success: bool,
+    },
+    Diagnostic {
+        project: &'a str,
+        diagnostic: &'a Diagnostic,
+    },
+    LinkFailed {
+        project: &'a str,
+        message: &'a str,
+    },
+    CommandFailed {
+        project: &'a str,
+        command: &'a str,
+        message: &'a str,
+    },
+    ProjectFinished {
+        project: &'a str,
+        fresh: bool,
+    },
+    BuildFinished {
+        success: bool,
+        files_compiled: usize,
+        errors: usize,
+        warnings: usize,
+    },
+}
+
+pub struct Reporter {
+    format: MessageFormat,
+    files_compiled: usize,
+    errors: usize,
+    warnings: usize,
+    pub timings: Timings,
+}
+
+impl Reporter {
+    pub fn new(format: MessageFormat) -> Self {
+        Self {
+            format,
+            files_compiled: 0,
+            errors: 0,
+            warnings: 0,
+            timings: Timings::new(),
+        }
+    }
+
+    pub fn is_human(&self) -> bool {
+        self.format == MessageFormat::Human
+    }
+
+    fn emit(&self, message: &Message) {
+        if self.format == MessageFormat::Json {
+            println!("{}", serde_json::to_string(message).unwrap());
+        }
+    }
+
+    fn emit_diagnostics(&mut self, project: &str, output: &str) {
+        for diagnostic in Diagnostic::parse(output) {
+            match diagnostic.severity.as_str() {
+                "error" | "fatal error" => self.errors += 1,
+                "warning" => self.warnings += 1,
+                _ => {}
+            }
+
+            self.emit(&Message::Diagnostic {
+                project,
+                diagnostic: &diagnostic,
+            });
+        }
+    }
+
+    pub fn project_started(&mut self, project: &str) {
+        self.emit(&Message::ProjectStarted { project });
+    }
+
+    pub fn file_compiled(&mut self, project: &str, file: &str, output: &str, started: Instant) {
+        self.files_compiled += 1;
+        self.timings
+            .record(TimingKind::Compile, project, file, started);
+
+        // Successful compiles can still produce warnings
+        self.emit_diagnostics(project, output);
+        self.emit(&Message::FileCompiled {
+            project,
+            file,
+            success: true,
+        });
+    }
+
+    pub fn compile_failed(&mut self, project: &str, file: &str, output: &str) {
+        if self.is_human() {
+            eprintln!(
+                "{}{}{}",
+                color::Fg(color::Red),
+                output,
+                color::Fg(color::Reset)
+            );
+        }
+
+        self.emit_diagnostics(project, output);
+        self.emit(&Message::FileCompiled {
+            project,
+            file,
+            success: false,
+        });
+    }
+
+    pub fn link_finished(&mut self, project: &str, output: &str, started: Instant) {
+        self.timings
+            .record(TimingKind::Link, project, output, started);
+    }
+
+    pub fn link_failed(&mut self, project: &str, output: &str) {
+        self.errors += 1;
+
+        if self.is_human() {
+            eprintln!(
+                "{}{}{}",
+                color::Fg(color::Red),
+                output,
+                color::Fg(color::Reset)
+            );
+        }
+
+        self.emit(&Message::LinkFailed {
+            project,
+            message: output,
+        });
+    }
+
+    pub fn command_failed(&mut self, project: &str, command: &str, output: &str) {
+        self.errors += 1;
+
+        if self.is_human() {
+            eprintln!(
+                "{}Command '{}' failed{}\n{}",
+                color::Fg(color::Red),
+                command,
+                color::Fg(color::Reset),
+                output
+            );
+        }
+
+        self.emit(&Message::CommandFailed {
+            project,
+            command,
+            message: output,
+        });
+    }
+
+    pub fn project_finished(&mut self, project: &str, fresh: bool) {
+        self.emit(&Message::ProjectFinished { project, fresh });
+    }
+
+    pub fn finish(&self, success: bool) {
+        self.emit(&Message::BuildFinished {
+            success,
+            files_compiled: self.files_compiled,
+            errors: self.errors,
+            warnings: self.warnings,
+        });
+    }
+
+    pub fn fail(&self) -> ! {
+        // Report the summary before exiting so consumers always get a final event
+        self.finish(false);
+        std::process::exit(-1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_with_column() {
+        let diagnostic =
+            Diagnostic::parse_line("src/main.c:3:5: error: expected ';' before '}' token").unwrap();
+
+        assert_eq!(diagnostic.file, "src/main.c");
+        assert_eq!(diagnostic.line, 3);
+        assert_eq!(diagnostic.column, Some(5));
+        assert_eq!(diagnostic.severity, "error");
+        assert_eq!(diagnostic.message, "expected ';' before '}' token");
+    }
+
+    #[test]
+    fn parse_without_column() {
+        let diagnostic = Diagnostic::parse_line("src/main.c:12: warning: unused variable").unwrap();
+
+        assert_eq!(diagnostic.file, "src/main.c");
+        assert_eq!(diagnostic.line, 12);
+        assert_eq!(diagnostic.column, None);
+        assert_eq!(diagnostic.severity, "warning");
+    }
+
+    #[test]
+    fn parse_fatal_error() {
+        let diagnostic = Diagnostic::parse_line(
+            "src/main.c:1:10: fatal error: missing.h: No such file or directory",
+        )
+        .unwrap();
+
+        assert_eq!(diagnostic.line, 1);
+        assert_eq!(diagnostic.column, Some(10));
+        assert_eq!(diagnostic.severity, "fatal error");
+        assert_eq!(diagnostic.message, "missing.h: No such file or directory");
+    }
+
+    #[test]
+    fn parse_severity_in_message() {
+        let diagnostic =
+            Diagnostic::parse_line("a.c:1:2: warning: treating this: error: as text").unwrap();
+
+        assert_eq!(diagnostic.file, "a.c");
+        assert_eq!(diagnostic.severity, "warning");
+        assert_eq!(diagnostic.message, "treating this: error: as text");
+    }
+
+    #[test]
+    fn driver_lines_are_not_diagnostics() {
+        assert!(Diagnostic::parse_line("clang: error: no such file or directory: 'x.c'").is_none());
+        assert!(Diagnostic::parse_line("gcc: fatal error: no input files").is_none());
+        assert!(Diagnostic::parse_line("In file included from src/main.c:1:").is_none());
+    }
+
+    #[test]
+    fn parse_output() {
+        let output = "src/a.c: In function 'main':\n\
+                      src/a.c:2:3: warning: unused variable 'x'\n\
+                      \x20   2 |   int x;\n\
+                      src/a.c:4:1: note: declared here\n";
+        let diagnostics = Diagnostic::parse(output);
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].severity, "warning");
+        assert_eq!(diagnostics[1].severity, "note");
+    }
+}
@@ -0,0 +1,233 @@
+use crate::config::BuildConfig;
+use crate::project::ProjectKind;
+use crate::{get_all_dependencies, get_dependencies};
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn escape_path(path: &Path) -> String {
+    // Paths in build statements need their spaces, colons and dollars escaped
+    path.to_str()
+        .unwrap()
+        .replace('$', "$$")
+        .replace(' ', "$ ")
+        .replace(':', "$:")
+}
+
+fn join_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|x| escape_path(x))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn escape_arg(arg: &OsStr) -> String {
+    let arg = arg.to_str().unwrap().replace('$', "$$");
+
+    // Arguments end up in a shell command, so quote them when needed
+    if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || "'\"\\;&|<>()*?#".contains(c))
+    {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    } else {
+        arg
+    }
+}
+
+fn command_args(command: &Command) -> String {
+    command
+        .get_args()
+        .map(escape_arg)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub struct NinjaExport {
+    pub contents: String,
+    pub warnings: Vec<String>,
+}
+
+pub fn generate(config: &BuildConfig) -> Result<NinjaExport, String> {
+    let mut output = String::new();
+    let mut warnings = vec![];
+
+    // The rules only forward the arguments buldr would use itself
+    writeln!(output, "# Generated by buldr, do not edit").unwrap();
+    writeln!(output, "rule compile").unwrap();
+    writeln!(
+        output,
+        "  command = $launcher $compiler $args -MMD -MF $out.d"
+    )
+    .unwrap();
+    writeln!(output, "  depfile = $out.d").unwrap();
+    writeln!(output, "  deps = gcc").unwrap();
+    writeln!(output, "  description = Compiling $in").unwrap();
+    writeln!(output).unwrap();
+    writeln!(output, "rule archive").unwrap();
+    writeln!(output, "  command = rm -f $out && $packer $args").unwrap();
+    writeln!(output, "  description = Archiving $out").unwrap();
+    writeln!(output).unwrap();
+    writeln!(output, "rule link").unwrap();
+    writeln!(output, "  command = $linker $args").unwrap();
+    writeln!(output, "  description = Linking $out").unwrap();
+    writeln!(output).unwrap();
+    writeln!(output, "rule generate").unwrap();
+    writeln!(output, "  command = $generator").unwrap();
+    writeln!(output, "  description = Generating $out").unwrap();
+    writeln!(output).unwrap();
+
+    for project in &config.projects {
+        // The hooks run around buldr's own build steps, which ninja has no equivalent for
+        for (name, hooks) in [
+            ("pre_build", &project.pre_build),
+            ("post_build", &project.post_build),
+        ] {
+            if let Some(hooks) = hooks.as_ref().filter(|x| !x.is_empty()) {
+                warnings.push(format!(
+                    "{} hooks {:?} of project '{}' can't be translated",
+                    name, hooks, project.name
+                ));
+            }
+        }
+
+        let source_files = project.get_all_source_files(&config.config);
+
+        // Generators run before anything of the project is compiled, they can generate headers
+        for generator in project.generate.iter().flatten() {
+            let (inputs, outputs, command) = project.expand_generator(generator, &config.config);
+            writeln!(
+                output,
+                "build {}: generate {}",
+                join_paths(&outputs),
+                join_paths(&inputs)
+            )
+            .unwrap();
+            writeln!(output, "  generator = {}", command.replace('$', "$$")).unwrap();
+        }
+        let generated_files = join_paths(&project.get_generated_files(&config.config));
+
+        // The precompiled header has to exist before any object file is compiled
+        let precompiled_header = project.get_precompiled_header_args(&config.config)?;
+        if let (Some(header), Some((header_output, args))) =
+            (&project.precompiled_header, &precompiled_header)
+        {
+            write!(
+                output,
+                "build {}: compile {}",
+                escape_path(header_output),
+                escape_path(header)
+            )
+            .unwrap();
+            if !generated_files.is_empty() {
+                write!(output, " || {}", generated_files).unwrap();
+            }
+            writeln!(output).unwrap();
+            writeln!(output, "  compiler = {}", config.config.compiler()).unwrap();
+            if let Some(launcher) = project.get_compiler_launcher(&config.config) {
+                writeln!(output, "  launcher = {}", launcher).unwrap();
+            }
+            writeln!(
+                output,
+                "  args = {}",
+                args.iter()
+                    .map(|x| escape_arg(OsStr::new(x)))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            )
+            .unwrap();
+        }
+
+        // Add an edge for every object file
+        let mut object_files = vec![];
+        for command in project.get_compile_commands(&source_files[..], &config.config)? {
+            let object_file = project.get_output_file(command.source_file.path(), &config.config);
+
+            write!(
+                output,
+                "build {}: compile {}",
+                escape_path(&object_file),
+                escape_path(command.source_file.path())
+            )
+            .unwrap();
+            if let Some((header_output, _)) = &precompiled_header {
+                write!(output, " | {}", escape_path(header_output)).unwrap();
+            }
+            if !generated_files.is_empty() {
+                write!(output, " || {}", generated_files).unwrap();
+            }
+            writeln!(output).unwrap();
+            writeln!(output, "  compiler = {}", command.compiler()).unwrap();
+            if let Some(launcher) = &command.launcher {
+                writeln!(output, "  launcher = {}", launcher).unwrap();
+            }
+            writeln!(
+                output,
+                "  args = {}",
+                command
+                    .args()
+                    .iter()
+                    .map(|x| escape_arg(OsStr::new(x)))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            )
+            .unwrap();
+
+            object_files.push(escape_path(&object_file));
+        }
+
+        // The project artifact has to wait for the artifacts of its dependencies
+        let dependencies: Vec<_> = get_dependencies(&config.projects, project)
+            .iter()
+            .map(|x| escape_path(&x.get_artifact(&config.config)))
+            .collect();
+
+        let link_command = project.get_link_command(
+            &source_files,
+            &get_all_dependencies(&config.projects, project),
+            &config.config,
+        )?;
+        let program = link_command.get_program().to_str().unwrap();
+        let (rule, variable) = match project.kind {
+            ProjectKind::Executable | ProjectKind::Test => ("link", "linker"),
+            ProjectKind::Library => ("archive", "packer"),
+        };
+
+        let artifact = escape_path(&project.get_artifact(&config.config));
+        write!(
+            output,
+            "build {}: {} {}",
+            artifact,
+            rule,
+            object_files.join(" ")
+        )
+        .unwrap();
+        if !dependencies.is_empty() {
+            write!(output, " | {}", dependencies.join(" ")).unwrap();
+        }
+        writeln!(output).unwrap();
+        writeln!(output, "  {} = {}", variable, program).unwrap();
+        writeln!(output, "  args = {}", command_args(&link_command)).unwrap();
+
+        // Allow building a project by its name
+        writeln!(output, "build {}: phony {}", project.name, artifact).unwrap();
+        writeln!(output).unwrap();
+    }
+
+    if let Some(project) = config.projects.iter().find(|x| x.default == Some(true)) {
+        writeln!(output, "default {}", project.name).unwrap();
+    }
+
+    // Flag everything that was lost in the generated file as well
+    if !warnings.is_empty() {
+        writeln!(output).unwrap();
+        for warning in &warnings {
+            writeln!(output, "# buldr: {}", warning).unwrap();
+        }
+    }
+
+    Ok(NinjaExport {
+        contents: output,
+        warnings,
+    })
+}
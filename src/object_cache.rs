@@ -0,0 +1,277 @@
+use crate::compile_command::CompileCommand;
+use crate::config::Config;
+use crate::remote_cache::RemoteCache;
+use crate::toolchain;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+const DEFAULT_MAX_SIZE: u64 = 5 * 1024;
+const STATS_FILE: &str = "stats.json";
+
+#[derive(Default, Serialize, Deserialize)]
+struct Counters {
+    hits: u64,
+    misses: u64,
+    #[serde(default)]
+    remote_hits: u64,
+}
+
+pub struct Stats {
+    pub dir: PathBuf,
+    pub entries: usize,
+    pub size: u64,
+    pub max_size: u64,
+    pub hits: u64,
+    pub remote_hits: u64,
+    pub misses: u64,
+}
+
+pub struct ObjectCache {
+    dir: PathBuf,
+    // In bytes
+    max_size: u64,
+    counters: Counters,
+    remote: Option<RemoteCache>,
+}
+
+pub fn default_dir() -> PathBuf {
+    // Follow the XDG base directories, falling back to ~/.cache
+    match std::env::var_os("XDG_CACHE_HOME").filter(|x| !x.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("buldr"),
+        None => PathBuf::from(std::env::var_os("HOME").unwrap_or_default())
+            .join(".cache")
+            .join("buldr"),
+    }
+}
+
+fn get_entries(dir: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().and_then(|x| x.to_str()) == Some("o"))
+        .filter_map(|e| {
+            let metadata = e.path().metadata().ok()?;
+            Some((
+                e.path().to_path_buf(),
+                metadata.len(),
+                metadata.modified().ok()?,
+            ))
+        })
+        .collect()
+}
+
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), std::io::Error> {
+    // Write next to the destination first, so a concurrent build never sees half a file
+    let temp = path.with_extension(format!("tmp{}", std::process::id()));
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(&temp, contents)?;
+    std::fs::rename(&temp, path)
+}
+
+impl ObjectCache {
+    pub fn new(config: &Config) -> Option<Self> {
+        // A remote cache goes through the local one as well
+        match (config.object_cache, &config.remote_cache) {
+            (Some(true), _) | (_, Some(_)) => Some(Self::open(Some(config))),
+            _ => None,
+        }
+    }
+
+    pub fn open(config: Option<&Config>) -> Self {
+        let dir = config
+            .and_then(|x| x.object_cache_dir.clone())
+            .unwrap_or_else(default_dir);
+        let max_size = config
+            .and_then(|x| x.object_cache_size)
+            .unwrap_or(DEFAULT_MAX_SIZE);
+        let counters = std::fs::read_to_string(dir.join(STATS_FILE))
+            .ok()
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default();
+
+        let remote = config.and_then(|x| {
+            x.remote_cache
+                .as_ref()
+                .and_then(|url| RemoteCache::new(url, x.remote_cache_mode))
+        });
+
+        ObjectCache {
+            dir,
+            max_size: max_size * 1024 * 1024,
+            counters,
+            remote,
+        }
+    }
+
+    pub fn key(&self, command: &CompileCommand) -> Option<String> {
+        // Preprocess with the same flags, which pulls in the contents of every header
+        let output = Command::new(command.compiler())
+            .arg("-E")
+            .args(command.flags())
+            .arg(command.source_file.path())
+            .output()
+            .ok()?;
+
+        // Files which can't be preprocessed are simply compiled, the error shows up then
+        if !output.status.success() {
+            return None;
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(toolchain::get_version(command.compiler()));
+        for arg in &command.arguments {
+            hasher.update(arg);
+            hasher.update([0]);
+        }
+        hasher.update(&output.stdout);
+
+        // Clang doesn't expand a precompiled header when preprocessing, so its contents are
+        // hashed instead
+        let arguments = command.arguments.iter();
+        for (arg, path) in arguments.clone().zip(arguments.skip(1)) {
+            if arg == "-include-pch" {
+                hasher.update(std::fs::read(path).ok()?);
+            }
+        }
+
+        Some(
+            hasher
+                .finalize()
+                .iter()
+                .map(|x| format!("{:02x}", x))
+                .collect(),
+        )
+    }
+
+    fn get_entry_path(&self, key: &str) -> PathBuf {
+        // Spread the entries over subdirectories so none of them gets too big
+        self.dir.join(&key[..2]).join(format!("{}.o", key))
+    }
+
+    fn fetch_remote(&mut self, key: &str) -> bool {
+        let remote = match &self.remote {
+            Some(remote) => remote,
+            None => return false,
+        };
+
+        let entry = self.get_entry_path(key);
+        let object = match remote.get(&format!("{}.o", key)) {
+            Some(object) => object,
+            None => return false,
+        };
+
+        // Keep the object locally, the next build doesn't have to ask again
+        let stderr = remote.get(&format!("{}.stderr", key));
+        let stored = write_atomic(&entry, &object).and_then(|_| match stderr {
+            Some(stderr) => write_atomic(&entry.with_extension("stderr"), &stderr),
+            None => Ok(()),
+        });
+
+        match stored {
+            Ok(_) => {
+                self.counters.remote_hits += 1;
+                true
+            }
+            Err(e) => {
+                eprintln!("warning: failed to store object in cache: {}", e);
+                false
+            }
+        }
+    }
+
+    pub fn get(&mut self, key: &str, output_file: &Path) -> Option<String> {
+        let entry = self.get_entry_path(key);
+
+        if !entry.exists() && !self.fetch_remote(key) {
+            self.counters.misses += 1;
+            return None;
+        }
+
+        if std::fs::copy(&entry, output_file).is_err() {
+            self.counters.misses += 1;
+            return None;
+        }
+
+        // Mark the entry as recently used, eviction removes the oldest ones first
+        if let Ok(file) = std::fs::File::options().write(true).open(&entry) {
+            file.set_modified(SystemTime::now()).ok();
+        }
+
+        self.counters.hits += 1;
+
+        // Replay the warnings of the original compilation
+        Some(std::fs::read_to_string(entry.with_extension("stderr")).unwrap_or_default())
+    }
+
+    pub fn put(&self, key: &str, output_file: &Path, stderr: &str) {
+        let entry = self.get_entry_path(key);
+
+        let object = match std::fs::read(output_file) {
+            Ok(object) => object,
+            Err(_) => return,
+        };
+
+        // A failing cache should never fail the build
+        let stored = write_atomic(&entry, &object).and_then(|_| match stderr.is_empty() {
+            true => Ok(()),
+            false => write_atomic(&entry.with_extension("stderr"), stderr.as_bytes()),
+        });
+
+        if let Err(e) = stored {
+            eprintln!("warning: failed to store object in cache: {}", e);
+        }
+
+        if let Some(remote) = &self.remote {
+            remote.put(&format!("{}.o", key), &object);
+            if !stderr.is_empty() {
+                remote.put(&format!("{}.stderr", key), stderr.as_bytes());
+            }
+        }
+    }
+
+    pub fn finish(&mut self) {
+        // Remove the least recently used entries until the cache fits again
+        let mut entries = get_entries(&self.dir);
+        let mut size: u64 = entries.iter().map(|x| x.1).sum();
+
+        if size > self.max_size {
+            entries.sort_by_key(|x| x.2);
+
+            for (path, length, _) in entries {
+                if size <= self.max_size {
+                    break;
+                }
+
+                std::fs::remove_file(&path).ok();
+                std::fs::remove_file(path.with_extension("stderr")).ok();
+                size -= length;
+            }
+        }
+
+        if std::fs::create_dir_all(&self.dir).is_ok() {
+            std::fs::write(
+                self.dir.join(STATS_FILE),
+                serde_json::to_string(&self.counters).unwrap(),
+            )
+            .ok();
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        let entries = get_entries(&self.dir);
+
+        Stats {
+            dir: self.dir.clone(),
+            entries: entries.len(),
+            size: entries.iter().map(|x| x.1).sum(),
+            max_size: self.max_size,
+            hits: self.counters.hits,
+            remote_hits: self.counters.remote_hits,
+            misses: self.counters.misses,
+        }
+    }
+}
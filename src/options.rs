@@ -0,0 +1,106 @@
+use crate::toolchain::CompilerFamily;
+use serde_derive::{Deserialize, Serialize};
+
+// Settings which mean the same for every compiler, translated to the flags of each one
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct BuildOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language_standard: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optimize: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug_info: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warnings: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warnings_as_errors: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lto: Option<bool>,
+}
+
+impl BuildOptions {
+    pub fn merge(&self, overrides: &BuildOptions) -> BuildOptions {
+        // Every option that is set in the overrides wins
+        BuildOptions {
+            language_standard: overrides
+                .language_standard
+                .clone()
+                .or_else(|| self.language_standard.clone()),
+            optimize: overrides.optimize.clone().or_else(|| self.optimize.clone()),
+            debug_info: overrides.debug_info.or(self.debug_info),
+            warnings: overrides.warnings.clone().or_else(|| self.warnings.clone()),
+            warnings_as_errors: overrides.warnings_as_errors.or(self.warnings_as_errors),
+            lto: overrides.lto.or(self.lto),
+        }
+    }
+
+    fn optimize_flag(&self, family: CompilerFamily) -> Option<String> {
+        self.optimize
+            .as_ref()
+            .map(|level| match (level.as_str(), family) {
+                // GCC only knows -Oz since version 12, -Os is the closest everywhere
+                ("z", CompilerFamily::Gcc) => String::from("-Os"),
+                (level, _) => format!("-O{}", level),
+            })
+    }
+
+    pub fn get_standard_flag(&self, cpp: bool) -> Option<String> {
+        // A standard only applies to its own language, `-std=c++17` is an error for C sources
+        let standard = self.language_standard.as_ref()?;
+        (standard.contains("++") == cpp).then(|| format!("-std={}", standard))
+    }
+
+    pub fn get_compile_flags(&self, family: CompilerFamily) -> Vec<String> {
+        let mut flags = vec![];
+
+        flags.extend(self.optimize_flag(family));
+
+        if self.debug_info == Some(true) {
+            flags.push(String::from("-g"));
+        }
+
+        let warnings: &[&str] = match (self.warnings.as_deref(), family) {
+            (None, _) | (Some("default"), _) => &[],
+            (Some("none"), _) => &["-w"],
+            (Some("all"), _) => &["-Wall"],
+            (Some("extra"), _) => &["-Wall", "-Wextra"],
+            (Some("pedantic"), _) => &["-Wall", "-Wextra", "-Wpedantic"],
+            (Some("everything"), CompilerFamily::Clang) => &["-Weverything"],
+            (Some("everything"), CompilerFamily::Gcc) => &["-Wall", "-Wextra", "-Wpedantic"],
+            (Some(other), _) => {
+                eprintln!(
+                    "warning: unknown warnings level '{}', expected none, default, all, extra, pedantic or everything",
+                    other
+                );
+                &[]
+            }
+        };
+        flags.extend(warnings.iter().map(|x| x.to_string()));
+
+        if self.warnings_as_errors == Some(true) {
+            flags.push(String::from("-Werror"));
+        }
+
+        if self.lto == Some(true) {
+            flags.push(String::from("-flto"));
+        }
+
+        flags
+    }
+
+    pub fn get_link_flags(&self, family: CompilerFamily) -> Vec<String> {
+        let mut flags = vec![];
+
+        // With LTO the code is generated while linking, so it needs the optimization level too
+        if self.lto == Some(true) {
+            flags.push(String::from("-flto"));
+            flags.extend(self.optimize_flag(family));
+        }
+
+        if self.debug_info == Some(true) {
+            flags.push(String::from("-g"));
+        }
+
+        flags
+    }
+}
@@ -0,0 +1,158 @@
+use crate::project::{Project, ProjectKind};
+use std::fmt::Write;
+use std::process::Command;
+
+const PKG_CONFIG: &str = "pkg-config";
+const DEFAULT_VERSION: &str = "0.1.0";
+
+fn run(requirements: &[String], args: &[&str]) -> Result<String, String> {
+    let output = Command::new(PKG_CONFIG)
+        .args(args)
+        .args(requirements)
+        .output()
+        .map_err(|e| format!("failed to run {}: {}", PKG_CONFIG, e))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8(output.stdout).unwrap())
+    } else {
+        Err(String::from_utf8(output.stderr).unwrap().trim().to_string())
+    }
+}
+
+pub fn check(requirements: &[String]) -> Result<(), String> {
+    // Check each requirement on its own so the error names the one that failed
+    for requirement in requirements {
+        run(
+            std::slice::from_ref(requirement),
+            &["--exists", "--print-errors", "--short-errors"],
+        )
+        .map_err(|e| format!("pkg-config requirement '{}' not met: {}", requirement, e))?;
+    }
+
+    Ok(())
+}
+
+pub fn get_cflags(requirements: &[String]) -> Result<Vec<String>, String> {
+    Ok(run(requirements, &["--cflags"])?
+        .split_whitespace()
+        .map(|x| x.to_string())
+        .collect())
+}
+
+pub fn get_libs(requirements: &[String]) -> Result<Vec<String>, String> {
+    Ok(run(requirements, &["--libs"])?
+        .split_whitespace()
+        .map(|x| x.to_string())
+        .collect())
+}
+
+pub struct PcLocation {
+    pub prefix: String,
+    pub libdir: String,
+    pub includedir: String,
+    pub include_dirs: Vec<String>,
+}
+
+pub fn generate_pc(project: &Project, dependencies: &[&Project], location: &PcLocation) -> String {
+    let mut cflags: Vec<String> = location
+        .include_dirs
+        .iter()
+        .map(|x| format!("-I{}", x))
+        .collect();
+    cflags.extend(project.defines.iter().flatten().map(|x| format!("-D{}", x)));
+
+    // Static libraries need everything they depend on on the link line as well, after the
+    // libraries using them. The dependencies come before their users, so walk them backwards
+    let mut libs = vec![String::from("-L${libdir}"), format!("-l{}", project.name)];
+    for x in dependencies.iter().rev() {
+        if let ProjectKind::Library = x.kind {
+            libs.push(format!("-l{}", x.name));
+        }
+    }
+
+    let mut requires = vec![];
+    for x in std::iter::once(project).chain(dependencies.iter().rev().copied()) {
+        for link in x.links.iter().flatten() {
+            let link = format!("-l{}", link);
+            if !libs.contains(&link) {
+                libs.push(link);
+            }
+        }
+
+        for requirement in x.pkg_config.iter().flatten() {
+            if !requires.contains(requirement) {
+                requires.push(requirement.clone());
+            }
+        }
+    }
+
+    let mut output = String::new();
+    writeln!(output, "prefix={}", location.prefix).unwrap();
+    writeln!(output, "libdir={}", location.libdir).unwrap();
+    writeln!(output, "includedir={}", location.includedir).unwrap();
+    writeln!(output).unwrap();
+    writeln!(output, "Name: {}", project.name).unwrap();
+    writeln!(
+        output,
+        "Description: {}",
+        project
+            .description
+            .clone()
+            .unwrap_or_else(|| format!("The {} library", project.name))
+    )
+    .unwrap();
+    writeln!(
+        output,
+        "Version: {}",
+        project.version.as_deref().unwrap_or(DEFAULT_VERSION)
+    )
+    .unwrap();
+    if !requires.is_empty() {
+        writeln!(output, "Requires: {}", requires.join(", ")).unwrap();
+    }
+    writeln!(output, "Cflags: {}", cflags.join(" ")).unwrap();
+    writeln!(output, "Libs: {}", libs.join(" ")).unwrap();
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library(name: &str, depends: &[&str], links: &[&str]) -> Project {
+        toml::from_str(&format!(
+            "name = {:?}\nkind = \"library\"\nsrc = []\ndepends = {:?}\nlinks = {:?}",
+            name, depends, links
+        ))
+        .unwrap()
+    }
+
+    fn get_libs_line(contents: &str) -> &str {
+        contents
+            .lines()
+            .find_map(|x| x.strip_prefix("Libs: "))
+            .unwrap()
+    }
+
+    #[test]
+    fn libraries_come_before_their_dependencies() {
+        let a = library("a", &["b"], &["m"]);
+        let b = library("b", &["zz"], &[]);
+        let zz = library("zz", &[], &["pthread"]);
+        let location = PcLocation {
+            prefix: String::from("/usr"),
+            libdir: String::from("${prefix}/lib"),
+            includedir: String::from("${prefix}/include"),
+            include_dirs: vec![],
+        };
+
+        // In the order the dependencies are built, the deepest one first
+        let contents = generate_pc(&a, &[&zz, &b], &location);
+
+        assert_eq!(
+            get_libs_line(&contents),
+            "-L${libdir} -la -lb -lzz -lm -lpthread"
+        );
+    }
+}
@@ -1,24 +1,68 @@
 use crate::compile_command::CompileCommand;
 use crate::config::Config;
+use crate::glob;
+use crate::install;
+use crate::message::Reporter;
+use crate::object_cache::ObjectCache;
+use crate::options::BuildOptions;
+use crate::pkg_config;
+use crate::toolchain::{self, CompilerFamily};
 use crate::Cache;
 use indicatif::{ProgressBar, ProgressStyle};
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
-use std::path::Path;
 use std::path::PathBuf;
+use std::path::{Component, Path};
 use std::process::Command;
-use termion::color;
+use std::time::{Instant, SystemTime};
 use walkdir::DirEntry;
 use walkdir::WalkDir;
 
-#[derive(Debug, Deserialize)]
+const HEADER_EXTENSIONS: &[&str] = &["h", "hh", "hpp", "hxx", "inl"];
+const SOURCE_EXTENSIONS: &[&str] = &["c", "cc", "cpp", "cxx"];
+const DEFAULT_UNITY_BATCH_SIZE: usize = 8;
+
+fn get_relative_path(path: &Path, dir: &Path) -> PathBuf {
+    // Both paths are absolute, so they at least share the root
+    let common = path
+        .components()
+        .zip(dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = PathBuf::new();
+    for _ in dir.components().skip(common) {
+        relative.push("..");
+    }
+    relative.extend(path.components().skip(common));
+    relative
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ProjectKind {
+    #[default]
     Executable,
     Library,
+    Test,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RunSettings {
+    pub working_dir: Option<PathBuf>,
+    pub env: Option<BTreeMap<String, String>>,
+    pub args: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Generator {
+    pub command: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<PathBuf>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Project {
     pub name: String,
     pub kind: ProjectKind,
@@ -29,15 +73,48 @@ pub struct Project {
     pub defines: Option<Vec<String>>,
     pub depends: Option<Vec<String>>,
     pub default: Option<bool>,
+    pub run: Option<RunSettings>,
+    pub public_headers: Option<Vec<PathBuf>>,
+    pub pkg_config: Option<Vec<String>>,
+    pub version: Option<String>,
+    pub description: Option<String>,
+    pub pre_build: Option<Vec<String>>,
+    pub post_build: Option<Vec<String>>,
+    pub generate: Option<Vec<Generator>>,
+    pub precompiled_header: Option<PathBuf>,
+    pub unity: Option<bool>,
+    pub unity_batch_size: Option<usize>,
+    pub compiler_launcher: Option<String>,
+    #[serde(flatten)]
+    pub options: BuildOptions,
+}
+
+// A generated source which includes a batch of the project's sources
+struct UnityBatch {
+    file: DirEntry,
+    sources: Vec<DirEntry>,
 }
 
 impl Project {
-    fn get_output_file(&self, path: &Path, config: &Config) -> PathBuf {
-        let mut output_file = Path::new(&config.obj).join(&self.name).join(path);
+    pub fn get_output_file(&self, path: &Path, config: &Config) -> PathBuf {
+        if path.starts_with(self.get_obj_dir(config)) {
+            return self.get_internal_output_file(path);
+        }
+
+        let mut output_file = self.get_obj_dir(config).join(path);
         output_file.set_extension("o");
         output_file
     }
 
+    fn get_internal_output_file(&self, path: &Path) -> PathBuf {
+        // Unity and generated sources are already written below the obj directory
+        path.with_extension("o")
+    }
+
+    fn get_obj_dir(&self, config: &Config) -> PathBuf {
+        Path::new(&config.obj).join(&self.name)
+    }
+
     fn is_valid_file(file_name: &OsStr, supported_types: &Option<Vec<String>>) -> bool {
         match supported_types {
             Some(supported_types) => {
@@ -72,18 +149,322 @@ impl Project {
         source_files
     }
 
+    pub fn uses_cpp(&self) -> bool {
+        self.get_source_files()
+            .iter()
+            .any(|x| toolchain::is_cpp(x.path()))
+    }
+
+    pub fn get_generated_dir(&self, config: &Config) -> PathBuf {
+        self.get_obj_dir(config).join("generated")
+    }
+
+    pub fn get_generated_files(&self, config: &Config) -> Vec<PathBuf> {
+        let generated_dir = self.get_generated_dir(config);
+
+        self.generate
+            .iter()
+            .flatten()
+            .flat_map(|x| x.outputs.iter().map(|y| generated_dir.join(y)))
+            .collect()
+    }
+
+    pub fn get_generated_sources(&self, config: &Config) -> Vec<PathBuf> {
+        self.get_generated_files(config)
+            .into_iter()
+            .filter(|path| {
+                path.extension()
+                    .and_then(|x| x.to_str())
+                    .map(|x| SOURCE_EXTENSIONS.contains(&x))
+                    .unwrap_or(false)
+            })
+            .collect()
+    }
+
+    pub fn get_all_source_files(&self, config: &Config) -> Vec<DirEntry> {
+        let mut source_files = self.get_source_files();
+
+        // Generated sources which already exist are compiled like any other source
+        for path in self.get_generated_sources(config) {
+            if path.is_file() {
+                source_files.extend(WalkDir::new(path).into_iter().filter_map(|e| e.ok()));
+            }
+        }
+
+        source_files
+    }
+
+    pub fn expand_generator(
+        &self,
+        generator: &Generator,
+        config: &Config,
+    ) -> (Vec<PathBuf>, Vec<PathBuf>, String) {
+        let generated_dir = self.get_generated_dir(config);
+
+        let inputs: Vec<PathBuf> = generator
+            .inputs
+            .iter()
+            .flat_map(|x| glob::expand(x))
+            .collect();
+        let outputs: Vec<PathBuf> = generator
+            .outputs
+            .iter()
+            .map(|x| generated_dir.join(x))
+            .collect();
+
+        let join = |paths: &[PathBuf]| {
+            paths
+                .iter()
+                .map(|x| x.to_str().unwrap())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let command = generator
+            .command
+            .replace("{inputs}", &join(&inputs))
+            .replace("{outputs}", &join(&outputs))
+            .replace("{out_dir}", generated_dir.to_str().unwrap());
+
+        (inputs, outputs, command)
+    }
+
+    pub fn run_generators(&self, config: &Config, reporter: &mut Reporter) {
+        for generator in self.generate.iter().flatten() {
+            let (inputs, outputs, command) = self.expand_generator(generator, config);
+
+            // Only regenerate when an output is missing or older than one of the inputs, or the
+            // command changed since the outputs were generated
+            let command_file = outputs.first().map(|x| {
+                let mut name = x.file_name().unwrap().to_os_string();
+                name.push(".command");
+                x.with_file_name(name)
+            });
+            let modified = |path: &PathBuf| path.metadata().and_then(|x| x.modified()).ok();
+            let oldest_output = outputs.iter().map(modified).min().flatten();
+            let newest_input = inputs.iter().filter_map(modified).max();
+            let up_to_date = match (oldest_output, newest_input) {
+                (Some(output), Some(input)) => output >= input,
+                (Some(_), None) => true,
+                (None, _) => false,
+            } && command_file
+                .as_ref()
+                .and_then(|x| std::fs::read_to_string(x).ok())
+                .as_deref()
+                == Some(command.as_str());
+
+            if up_to_date {
+                continue;
+            }
+
+            for output in &outputs {
+                std::fs::create_dir_all(output.parent().unwrap()).unwrap();
+            }
+
+            let output = match Command::new("sh").arg("-c").arg(&command).output() {
+                Ok(output) => output,
+                Err(e) => {
+                    reporter.command_failed(
+                        &self.name,
+                        &command,
+                        &format!("failed to run sh: {}", e),
+                    );
+                    reporter.fail();
+                }
+            };
+
+            if !output.status.success() {
+                // Outputs left behind by a failed run would look up to date to the next build
+                for output in outputs.iter().chain(&command_file) {
+                    std::fs::remove_file(output).ok();
+                }
+
+                let mut message = String::from_utf8_lossy(&output.stdout).to_string();
+                message.push_str(&String::from_utf8_lossy(&output.stderr));
+
+                reporter.command_failed(&self.name, &command, &message);
+                reporter.fail();
+            }
+
+            if let Some(command_file) = command_file {
+                std::fs::write(command_file, &command).unwrap();
+            }
+        }
+    }
+
+    pub fn get_header_files(&self) -> Vec<PathBuf> {
+        let is_header = |path: &Path| {
+            path.extension()
+                .and_then(|x| x.to_str())
+                .map(|x| HEADER_EXTENSIONS.contains(&x))
+                .unwrap_or(false)
+        };
+
+        // Headers live next to the sources or anywhere below the include directories
+        let src_dirs = self.src.iter().map(|x| (x.clone(), 1));
+        let include_dirs = self
+            .include
+            .iter()
+            .flatten()
+            .map(|x| (PathBuf::from(x), usize::MAX));
+
+        let mut header_files: Vec<PathBuf> = vec![];
+        for (dir, depth) in src_dirs.chain(include_dirs) {
+            for entry in WalkDir::new(dir)
+                .max_depth(depth)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file() && is_header(e.path()))
+            {
+                if !header_files.iter().any(|x| x == entry.path()) {
+                    header_files.push(entry.path().to_path_buf());
+                }
+            }
+        }
+
+        header_files
+    }
+
+    fn get_pkg_config_flags(
+        &self,
+        query: fn(&[String]) -> Result<Vec<String>, String>,
+    ) -> Result<Vec<String>, String> {
+        match &self.pkg_config {
+            Some(requirements) if !requirements.is_empty() => query(requirements),
+            _ => Ok(vec![]),
+        }
+    }
+
+    pub fn check_requirements(&self) -> Result<(), String> {
+        match &self.pkg_config {
+            Some(requirements) => pkg_config::check(requirements),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get_options(&self, config: &Config) -> BuildOptions {
+        // Options of the project win over the ones of the config
+        config.options.merge(&self.options)
+    }
+
+    pub fn get_compiler_launcher<'a>(&'a self, config: &'a Config) -> Option<&'a str> {
+        // Projects can use a different launcher than the rest, or none at all with ""
+        self.compiler_launcher
+            .as_deref()
+            .or(config.compiler_launcher.as_deref())
+            .filter(|x| !x.trim().is_empty())
+    }
+
+    fn get_compile_flags(&self, config: &Config) -> Result<Vec<String>, String> {
+        let mut flags = vec![];
+
+        // Add the include arguments
+        if let Some(include_dirs) = &self.include {
+            flags.extend(include_dirs.iter().map(|x| format!("-I{}", x)));
+        }
+
+        // Generated headers can be included like any other header
+        if self.generate.is_some() {
+            let mut generated_dirs: Vec<_> = self
+                .get_generated_files(config)
+                .iter()
+                .map(|x| format!("-I{}", x.parent().unwrap().to_str().unwrap()))
+                .collect();
+            generated_dirs.sort();
+            generated_dirs.dedup();
+            flags.append(&mut generated_dirs);
+        }
+
+        // Add the defines
+        if let Some(defines) = &self.defines {
+            flags.extend(defines.iter().map(|x| format!("-D{}", x)));
+        }
+
+        // Add the flags of the system dependencies
+        flags.append(&mut self.get_pkg_config_flags(pkg_config::get_cflags)?);
+
+        // The raw compiler options come last, so they can still override these
+        let family = toolchain::detect_family(config.compiler());
+        flags.append(&mut self.get_options(config).get_compile_flags(family));
+
+        if let Some(args) = &config.compiler_opts {
+            flags.extend(args.iter().cloned());
+        }
+
+        Ok(flags)
+    }
+
+    fn get_precompiled_header_output(&self, config: &Config) -> Option<(PathBuf, CompilerFamily)> {
+        self.precompiled_header.as_ref().map(|header| {
+            let family = toolchain::detect_family(config.compiler());
+            let extension = match family {
+                CompilerFamily::Clang => "pch",
+                CompilerFamily::Gcc => "gch",
+            };
+
+            // GCC looks for the precompiled header next to the header with an extra extension
+            let file_name = format!(
+                "{}.{}",
+                header.file_name().unwrap().to_str().unwrap(),
+                extension
+            );
+            let output = self.get_obj_dir(config).join("pch").join(file_name);
+
+            (output, family)
+        })
+    }
+
+    fn is_precompiled_header_cpp(&self) -> bool {
+        // Compile it as a C++ header when the project has any C++ sources
+        match &self.precompiled_header {
+            Some(header) => toolchain::is_cpp(header) || self.uses_cpp(),
+            None => false,
+        }
+    }
+
+    fn get_precompiled_header_flags(&self, config: &Config) -> Vec<String> {
+        match self.get_precompiled_header_output(config) {
+            Some((output, CompilerFamily::Clang)) => {
+                vec![
+                    String::from("-include-pch"),
+                    output.to_str().unwrap().to_string(),
+                ]
+            }
+            Some((output, CompilerFamily::Gcc)) => {
+                // Including the header path without the extension makes GCC pick up the .gch
+                let mut header = output.clone();
+                header.set_extension("");
+                vec![
+                    String::from("-include"),
+                    header.to_str().unwrap().to_string(),
+                    String::from("-Winvalid-pch"),
+                ]
+            }
+            None => vec![],
+        }
+    }
+
     pub fn get_compile_commands(
         &self,
         source_files: &[DirEntry],
         config: &Config,
-    ) -> Vec<CompileCommand> {
+    ) -> Result<Vec<CompileCommand>, String> {
+        // The flags are the same for all files of the project
+        let flags = self.get_compile_flags(config)?;
+        let precompiled_header_flags = self.get_precompiled_header_flags(config);
+        let options = self.get_options(config);
+
         let commands: Vec<_> = source_files
             .iter()
             .map(|source| {
                 let output_file = self.get_output_file(source.path(), config);
 
-                let mut command =
-                    CompileCommand::new(std::env::current_dir().unwrap(), &config.compiler, source);
+                let mut command = CompileCommand::new(
+                    std::env::current_dir().unwrap(),
+                    config.compiler(),
+                    source,
+                    &output_file,
+                );
+                command.launcher = self.get_compiler_launcher(config).map(String::from);
 
                 // Add the primary compile commands arguments
                 command.push_args(&[
@@ -93,54 +474,295 @@ impl Project {
                     output_file.to_str().unwrap(),
                 ]);
 
-                // Add the include arguments
-                if let Some(include_dirs) = &self.include {
-                    command.push_args(
-                        &include_dirs
-                            .iter()
-                            .map(|x| format!("-I{}", x))
-                            .collect::<Vec<_>>()[..],
-                    );
+                // A precompiled header can only be used by files of the same language, the
+                // others include the header itself
+                let cpp = toolchain::is_cpp(source.path());
+                match &self.precompiled_header {
+                    Some(header) if cpp != self.is_precompiled_header_cpp() => {
+                        command.push_args(&["-include", header.to_str().unwrap()]);
+                    }
+                    _ => command.push_args(&precompiled_header_flags),
                 }
+                if let Some(flag) = options.get_standard_flag(cpp) {
+                    command.push_args(&[flag]);
+                }
+                command.push_args(&flags);
+
+                command
+            })
+            .collect();
 
-                // Add the defines
-                if let Some(defines) = &self.defines {
-                    command.push_args(
-                        &defines
-                            .iter()
-                            .map(|x| format!("-D{}", x))
-                            .collect::<Vec<_>>()[..],
+        Ok(commands)
+    }
+
+    pub fn get_precompiled_header_args(
+        &self,
+        config: &Config,
+    ) -> Result<Option<(PathBuf, Vec<String>)>, String> {
+        let (header, output) = match (
+            &self.precompiled_header,
+            self.get_precompiled_header_output(config),
+        ) {
+            (Some(header), Some((output, _))) => (header, output),
+            _ => return Ok(None),
+        };
+
+        let language = if self.is_precompiled_header_cpp() {
+            "c++-header"
+        } else {
+            "c-header"
+        };
+
+        let mut args = vec![
+            String::from("-x"),
+            String::from(language),
+            header.to_str().unwrap().to_string(),
+            String::from("-o"),
+            output.to_str().unwrap().to_string(),
+        ];
+        args.extend(
+            self.get_options(config)
+                .get_standard_flag(self.is_precompiled_header_cpp()),
+        );
+        args.append(&mut self.get_compile_flags(config)?);
+
+        Ok(Some((output, args)))
+    }
+
+    fn build_precompiled_header(
+        &self,
+        config: &Config,
+        reporter: &mut Reporter,
+    ) -> Option<SystemTime> {
+        let (header, (output, args)) = match (
+            &self.precompiled_header,
+            self.get_precompiled_header_args(config),
+        ) {
+            (Some(header), Ok(Some(args))) => (header, args),
+            (_, Err(e)) => {
+                reporter.command_failed(&self.name, "pkg-config", &e);
+                reporter.fail();
+            }
+            _ => return None,
+        };
+
+        // GCC falls back to the header next to the .gch, and tools which can't read the .gch
+        // like the preprocessor need it as well
+        if let Some((_, CompilerFamily::Gcc)) = self.get_precompiled_header_output(config) {
+            let stub = output.with_extension("");
+            let levels = stub
+                .parent()
+                .unwrap()
+                .components()
+                .filter(|x| matches!(x, Component::Normal(_)))
+                .count();
+            let target = if stub.is_relative() && !stub.starts_with("..") {
+                Path::new(&"../".repeat(levels)).join(header)
+            } else {
+                std::fs::canonicalize(header).unwrap_or_else(|_| header.clone())
+            };
+            let contents = format!("#include \"{}\"\n", target.to_str().unwrap());
+
+            std::fs::create_dir_all(stub.parent().unwrap()).unwrap();
+            if std::fs::read_to_string(&stub).ok().as_deref() != Some(&contents) {
+                std::fs::write(&stub, contents).unwrap();
+            }
+        }
+
+        // Rebuild when the header is newer or the flags it was built with changed
+        let flags_file = output.with_extension("flags");
+        let flags = format!("{} {}", config.compiler(), args.join(" "));
+        let modified = |path: &Path| path.metadata().and_then(|x| x.modified()).ok();
+        let up_to_date = match (modified(&output), modified(header)) {
+            (Some(output_time), Some(header_time)) => {
+                output_time >= header_time
+                    && std::fs::read_to_string(&flags_file).ok().as_deref() == Some(flags.as_str())
+            }
+            _ => false,
+        };
+
+        if up_to_date {
+            return modified(&output);
+        }
+
+        std::fs::create_dir_all(output.parent().unwrap()).unwrap();
+
+        let started = Instant::now();
+        let result =
+            toolchain::compiler_command(self.get_compiler_launcher(config), config.compiler())
+                .args(&args)
+                .output();
+
+        let header_name = header.to_str().unwrap();
+        let stderr = match result {
+            Ok(result) if result.status.success() => String::from_utf8(result.stderr).unwrap(),
+            Ok(result) => {
+                reporter.compile_failed(
+                    &self.name,
+                    header_name,
+                    &String::from_utf8(result.stderr).unwrap(),
+                );
+                reporter.fail();
+            }
+            Err(e) => {
+                let message = format!("failed to run {}: {}", config.compiler(), e);
+                reporter.compile_failed(&self.name, header_name, &message);
+                reporter.fail();
+            }
+        };
+
+        reporter.file_compiled(&self.name, header_name, &stderr, started);
+        std::fs::write(flags_file, flags).unwrap();
+
+        modified(&output)
+    }
+
+    fn write_unity_batches(
+        &self,
+        source_files: &[DirEntry],
+        config: &Config,
+    ) -> Result<Vec<UnityBatch>, std::io::Error> {
+        let unity_dir = self.get_obj_dir(config).join("unity");
+        std::fs::create_dir_all(&unity_dir)?;
+
+        // Sources of different languages can't share a batch, and sorting them keeps the
+        // batches stable between builds
+        let mut groups: BTreeMap<String, Vec<&DirEntry>> = BTreeMap::new();
+        for source in source_files {
+            let extension = source
+                .path()
+                .extension()
+                .and_then(|x| x.to_str())
+                .unwrap_or("c")
+                .to_string();
+            groups.entry(extension).or_default().push(source);
+        }
+
+        let batch_size = self
+            .unity_batch_size
+            .unwrap_or(DEFAULT_UNITY_BATCH_SIZE)
+            .max(1);
+
+        let mut batches = vec![];
+        for (extension, mut sources) in groups {
+            sources.sort_by_key(|x| x.path().to_path_buf());
+
+            for chunk in sources.chunks(batch_size) {
+                let file = unity_dir.join(format!("unity_{}.{}", batches.len(), extension));
+
+                // The includes are resolved relative to the unity file. They don't depend on
+                // where the project is checked out, so the object cache can share the objects
+                let mut contents = String::new();
+                for source in chunk {
+                    let path = get_relative_path(
+                        &std::fs::canonicalize(source.path())?,
+                        &std::fs::canonicalize(&unity_dir)?,
                     );
+                    contents.push_str(&format!("#include \"{}\"\n", path.to_str().unwrap()));
                 }
 
-                if let Some(args) = &config.compiler_opts {
-                    command.push_args(args);
+                // The unity file records which sources belong to the batch, so it is only
+                // rewritten when that changes. Its time then tells the batch is out of date
+                if std::fs::read_to_string(&file).ok().as_deref() != Some(&contents) {
+                    std::fs::write(&file, contents)?;
                 }
 
-                command
-            })
-            .collect();
+                batches.push(UnityBatch {
+                    file: WalkDir::new(&file).into_iter().next().unwrap()?,
+                    sources: chunk.iter().map(|x| (*x).clone()).collect(),
+                });
+            }
+        }
+
+        Ok(batches)
+    }
 
-        commands
+    pub fn get_public_include_dirs(&self) -> Vec<PathBuf> {
+        match &self.public_headers {
+            // Header files are included through the include directory they're in, the same way
+            // as once they're installed
+            Some(headers) => headers
+                .iter()
+                .map(|x| {
+                    if x.is_dir() {
+                        x.clone()
+                    } else {
+                        let relative = install::get_header_path(self, x);
+                        let depth = relative.components().count();
+                        let mut dir = x.as_path();
+                        for _ in 0..depth {
+                            dir = dir.parent().unwrap_or_else(|| Path::new("."));
+                        }
+                        dir.to_path_buf()
+                    }
+                })
+                .collect(),
+            None => self.include.iter().flatten().map(PathBuf::from).collect(),
+        }
     }
 
-    pub fn link(&self, source_files: Vec<DirEntry>, config: &Config) -> Result<(), std::io::Error> {
-        // Determine the output directory
-        let output_dir = Path::new(&config.bin);
+    pub fn get_pc_file_name(&self) -> String {
+        format!("lib{}.pc", self.name)
+    }
 
-        // Create the output directory if it doesn't exist
-        std::fs::create_dir_all(output_dir)?;
+    pub fn get_artifact(&self, config: &Config) -> PathBuf {
+        match self.kind {
+            ProjectKind::Executable | ProjectKind::Test => Path::new(&config.bin).join(&self.name),
+            ProjectKind::Library => Path::new(&config.bin).join(format!("lib{}.a", self.name)),
+        }
+    }
+
+    pub fn get_run_command(&self, config: &Config) -> Result<Command, std::io::Error> {
+        let mut command = Command::new(std::fs::canonicalize(self.get_artifact(config))?);
+
+        // Apply the run settings of the project
+        if let Some(settings) = &self.run {
+            if let Some(working_dir) = &settings.working_dir {
+                command.current_dir(working_dir);
+            }
+
+            if let Some(env) = &settings.env {
+                command.envs(env);
+            }
 
+            if let Some(args) = &settings.args {
+                command.args(args);
+            }
+        }
+
+        Ok(command)
+    }
+
+    fn get_link_requirements(&self, dependencies: &[&Project]) -> Vec<String> {
+        // Libraries don't carry their system dependencies, whatever links them has to
+        let mut requirements: Vec<String> = vec![];
+        for x in dependencies.iter().copied().chain(std::iter::once(self)) {
+            for requirement in x.pkg_config.iter().flatten() {
+                if !requirements.contains(requirement) {
+                    requirements.push(requirement.clone());
+                }
+            }
+        }
+
+        requirements
+    }
+
+    pub fn get_link_command(
+        &self,
+        source_files: &[DirEntry],
+        dependencies: &[&Project],
+        config: &Config,
+    ) -> Result<Command, String> {
         // Get a list of all the object files
         let object_files: Vec<_> = source_files
             .iter()
             .map(|x| self.get_output_file(x.path(), config))
             .collect();
 
-        // Get the link command based on what kind of project this is
+        // Build the command based on what kind of project this is
         let mut link_command = match self.kind {
-            ProjectKind::Executable => {
-                let mut link_command = Command::new(&config.linker);
+            ProjectKind::Executable | ProjectKind::Test => {
+                let mut link_command = Command::new(config.linker());
 
                 // Add all the object files that have to be linked
                 link_command.args(object_files);
@@ -148,7 +770,7 @@ impl Project {
                 // Set up the output file main search directory
                 link_command
                     .arg("-o")
-                    .arg(&output_dir.join(&self.name))
+                    .arg(self.get_artifact(config))
                     .arg("-L")
                     .arg(&config.bin);
 
@@ -157,6 +779,15 @@ impl Project {
                     link_command.args(deps.iter().map(|x| format!("-l{}", x)).collect::<Vec<_>>());
                 }
 
+                // Add the libraries of the system dependencies
+                let requirements = self.get_link_requirements(dependencies);
+                if !requirements.is_empty() {
+                    link_command.args(pkg_config::get_libs(&requirements)?);
+                }
+
+                let family = toolchain::detect_family(config.linker());
+                link_command.args(self.get_options(config).get_link_flags(family));
+
                 // Add any other user-specified linker options
                 if let Some(args) = &config.linker_opts {
                     link_command.args(args);
@@ -165,12 +796,17 @@ impl Project {
                 link_command
             }
             ProjectKind::Library => {
-                let mut link_command = Command::new(&config.packer);
+                let mut link_command = Command::new(config.packer());
+
+                // Add any user-specified packer options
+                if let Some(args) = &config.packer_opts {
+                    link_command.args(args);
+                }
 
                 // Setup the output file name and object files
                 link_command
                     .arg("rcs")
-                    .arg(output_dir.join(format!("lib{}.a", self.name)))
+                    .arg(self.get_artifact(config))
                     .args(object_files);
 
                 link_command
@@ -182,68 +818,186 @@ impl Project {
             link_command.args(links.iter().map(|x| format!("-l{}", x)).collect::<Vec<_>>());
         }
 
+        Ok(link_command)
+    }
+
+    pub fn link(
+        &self,
+        source_files: Vec<DirEntry>,
+        dependencies: &[&Project],
+        config: &Config,
+        reporter: &mut Reporter,
+    ) -> Result<(), std::io::Error> {
+        // Create the output directory if it doesn't exist
+        std::fs::create_dir_all(&config.bin)?;
+
+        // Get the link command based on what kind of project this is
+        let mut link_command = match self.get_link_command(&source_files, dependencies, config) {
+            Ok(link_command) => link_command,
+            Err(e) => {
+                reporter.link_failed(&self.name, &e);
+                reporter.fail();
+            }
+        };
+
         // Execute the command and get the output
-        let output = link_command.output().expect("failed to link command");
+        let started = Instant::now();
+        let output = link_command.output();
 
         // If the link command didn't exit succesfully, print the error and exit
-        if !output.status.success() {
-            eprintln!(
-                "{}{}{}",
-                color::Fg(color::Red),
-                String::from_utf8(output.stderr).unwrap(),
-                color::Fg(color::Reset),
-            );
-            std::process::exit(-1);
+        match output {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => {
+                reporter.link_failed(&self.name, &String::from_utf8(output.stderr).unwrap());
+                reporter.fail();
+            }
+            Err(e) => {
+                let message = format!(
+                    "failed to run {}: {}",
+                    link_command.get_program().to_string_lossy(),
+                    e
+                );
+                reporter.link_failed(&self.name, &message);
+                reporter.fail();
+            }
         }
 
+        reporter.link_finished(&self.name, &self.name, started);
+
+        // Post-build hooks can work on the freshly linked output
+        self.run_hooks(&self.post_build, config, reporter);
+
         Ok(())
     }
 
+    fn run_hooks(&self, hooks: &Option<Vec<String>>, config: &Config, reporter: &mut Reporter) {
+        for hook in hooks.iter().flatten() {
+            // Hooks are shell commands which get to know what is being built
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(hook)
+                .env("BULDR_PROJECT", &self.name)
+                .env("BULDR_OUTPUT", self.get_artifact(config))
+                .env("BULDR_OBJ_DIR", self.get_obj_dir(config))
+                .env("BULDR_PROFILE", config.target.as_deref().unwrap_or("host"))
+                .output();
+            let output = match output {
+                Ok(output) => output,
+                Err(e) => {
+                    reporter.command_failed(&self.name, hook, &format!("failed to run sh: {}", e));
+                    reporter.fail();
+                }
+            };
+
+            if !output.status.success() {
+                let mut message = String::from_utf8_lossy(&output.stdout).to_string();
+                message.push_str(&String::from_utf8_lossy(&output.stderr));
+
+                reporter.command_failed(&self.name, hook, &message);
+                reporter.fail();
+            }
+        }
+    }
+
     pub fn build(
         &self,
         force_link: bool,
+        dependencies: &[&Project],
         cache: &mut Cache,
         config: &Config,
+        reporter: &mut Reporter,
     ) -> Result<bool, std::io::Error> {
+        reporter.project_started(&self.name);
+
+        // Run the pre-build hooks first, they might generate some of the sources
+        self.run_hooks(&self.pre_build, config, reporter);
+
+        // Bring the generated sources up to date
+        self.run_generators(config, reporter);
+
+        // Objects compiled before the precompiled header was last built are out of date, even
+        // when that build stopped before getting to them
+        let precompiled_header_time = self.build_precompiled_header(config, reporter);
+
         // Gathering source files
-        let source_files = self.get_source_files();
+        let source_files = self.get_all_source_files(config);
 
         // Check which source files we actually have to recompile
-        let source_files_to_recompile: Vec<_> = source_files
-            .iter()
-            .filter(|x| {
-                let time = x.metadata().unwrap().modified().unwrap();
-                cache.has_changed(&self.get_output_file(x.path(), config), &time)
-            })
-            .collect();
+        let (source_files, source_files_to_recompile): (Vec<_>, Vec<_>) =
+            if self.unity == Some(true) {
+                // A batch is recompiled when it or any of its sources changed since it was
+                // compiled, which still holds when an earlier build failed before getting to it
+                let batches = self.write_unity_batches(&source_files, config)?;
+                let to_recompile = batches
+                    .iter()
+                    .filter(|batch| {
+                        let output_file = self.get_output_file(batch.file.path(), config);
+                        precompiled_header_time.is_some_and(|x| cache.has_changed(&output_file, &x))
+                            || std::iter::once(&batch.file).chain(&batch.sources).any(|x| {
+                                let time = x.metadata().unwrap().modified().unwrap();
+                                cache.has_changed(&output_file, &time)
+                            })
+                    })
+                    .map(|batch| batch.file.clone())
+                    .collect();
+
+                (batches.into_iter().map(|x| x.file).collect(), to_recompile)
+            } else {
+                let to_recompile = source_files
+                    .iter()
+                    .filter(|x| {
+                        let time = x.metadata().unwrap().modified().unwrap();
+                        let output_file = self.get_output_file(x.path(), config);
+                        precompiled_header_time.is_some_and(|x| cache.has_changed(&output_file, &x))
+                            || cache.has_changed(&output_file, &time)
+                    })
+                    .cloned()
+                    .collect();
+
+                (source_files, to_recompile)
+            };
 
         // If there is nothing to do return
         if source_files_to_recompile.is_empty() {
             if force_link {
-                self.link(source_files, config)?;
+                self.link(source_files, dependencies, config, reporter)?;
             }
+            reporter.project_finished(&self.name, !force_link);
             return Ok(force_link);
         }
 
-        // Set up the progress bar
-        let progress_bar = ProgressBar::new(source_files_to_recompile.len() as u64);
+        // Set up the progress bar, it is hidden when emitting machine readable messages
+        let progress_bar = if reporter.is_human() {
+            ProgressBar::new(source_files_to_recompile.len() as u64)
+        } else {
+            ProgressBar::hidden()
+        };
         progress_bar.set_style(
             ProgressStyle::default_bar().template("{prefix:10} {bar:80} {pos:>5}/{len:5} {msg}"),
         );
         progress_bar.set_prefix(self.name.clone());
 
         // Fetch all the compile commands
-        let compile_commands = self.get_compile_commands(
-            &source_files_to_recompile
-                .iter()
-                .map(|x| (*x).clone())
-                .collect::<Vec<_>>()[..],
-            config,
-        );
+        let compile_commands = match self.get_compile_commands(&source_files_to_recompile, config) {
+            Ok(compile_commands) => compile_commands,
+            Err(e) => {
+                reporter.command_failed(&self.name, "pkg-config", &e);
+                reporter.fail();
+            }
+        };
+
+        // Objects compiled before, possibly in another checkout, don't have to be compiled again
+        let mut object_cache = ObjectCache::new(config);
 
         // Execute all compile commands
         for mut compile_command in compile_commands {
             // Set the current file we are compiling
+            let source_path = compile_command
+                .source_file
+                .path()
+                .to_str()
+                .unwrap()
+                .to_string();
             progress_bar.set_message(
                 compile_command
                     .source_file
@@ -257,8 +1011,25 @@ impl Project {
             let output_file = self.get_output_file(compile_command.source_file.path(), config);
             std::fs::create_dir_all(output_file.parent().unwrap())?;
 
-            match compile_command.execute() {
-                Ok(_) => {
+            let started = Instant::now();
+            let key = object_cache.as_ref().and_then(|x| x.key(&compile_command));
+            let cached = match (&mut object_cache, &key) {
+                (Some(object_cache), Some(key)) => object_cache.get(key, &output_file),
+                _ => None,
+            };
+            let result = match cached {
+                Some(output) => Ok(output),
+                None => compile_command.execute().inspect(|output| {
+                    if let (Some(object_cache), Some(key)) = (&object_cache, &key) {
+                        object_cache.put(key, &output_file, output);
+                    }
+                }),
+            };
+
+            match result {
+                Ok(output) => {
+                    reporter.file_compiled(&self.name, &source_path, &output, started);
+
                     // The command executed succesfully so we can update the build cache
                     cache.update(&output_file);
                     cache.write()?;
@@ -266,20 +1037,25 @@ impl Project {
                     progress_bar.inc(1);
                 }
                 Err(e) => {
-                    // The command failed so lets print an error message
-                    eprintln!("{}{}{}", color::Fg(color::Red), e, color::Fg(color::Reset),);
                     // Since compilation stops here, we can stop the progress bar
                     progress_bar.finish_and_clear();
+                    // The command failed so lets report the error
+                    reporter.compile_failed(&self.name, &source_path, &e);
                     // And exit the program
-                    std::process::exit(-1);
+                    reporter.fail();
                 }
             }
         }
         // Compilation succesful
         progress_bar.finish_with_message("done");
 
+        if let Some(object_cache) = &mut object_cache {
+            object_cache.finish();
+        }
+
         // Link all compiled object files
-        self.link(source_files, config)?;
+        self.link(source_files, dependencies, config, reporter)?;
+        reporter.project_finished(&self.name, false);
         Ok(true)
     }
 }
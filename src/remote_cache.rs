@@ -0,0 +1,451 @@
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Objects are far smaller, anything bigger is refused instead of being read into memory
+const MAX_ENTRY_SIZE: usize = 256 * 1024 * 1024;
+
+// Once a server couldn't be reached it isn't tried again for the rest of the build
+static UNREACHABLE: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoteCacheMode {
+    ReadOnly,
+    ReadWrite,
+}
+
+pub struct RemoteCache {
+    host: String,
+    port: u16,
+    prefix: String,
+    writable: bool,
+}
+
+fn is_valid_name(name: &str) -> bool {
+    // Only the files the object cache stores, which keeps requests out of other directories
+    match name.split_once('.') {
+        Some((key, extension)) => {
+            key.len() > 2
+                && key.chars().all(|c| c.is_ascii_hexdigit())
+                && ["o", "stderr"].contains(&extension)
+        }
+        None => false,
+    }
+}
+
+fn get_header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        match key.trim().eq_ignore_ascii_case(name) {
+            true => Some(value.trim()),
+            false => None,
+        }
+    })
+}
+
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    let invalid = || std::io::Error::other("invalid chunked response");
+    let mut decoded = vec![];
+
+    loop {
+        // Every chunk starts with its size in hex, optionally followed by extensions
+        let end = body
+            .windows(2)
+            .position(|x| x == b"\r\n")
+            .ok_or_else(invalid)?;
+        let line = String::from_utf8_lossy(&body[..end]);
+        let size = line.split(';').next().unwrap().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| invalid())?;
+        body = &body[end + 2..];
+
+        if size == 0 {
+            return Ok(decoded);
+        }
+        if body.len() < size + 2 || decoded.len() + size > MAX_ENTRY_SIZE {
+            return Err(invalid());
+        }
+
+        decoded.extend_from_slice(&body[..size]);
+        body = &body[size + 2..];
+    }
+}
+
+fn get_entry_path(dir: &Path, name: &str) -> PathBuf {
+    // The same layout as the local object cache, so a server can share its own cache
+    dir.join(&name[..2]).join(name)
+}
+
+impl RemoteCache {
+    pub fn new(url: &str, mode: Option<RemoteCacheMode>) -> Option<Self> {
+        let rest = match url.strip_prefix("http://") {
+            Some(rest) => rest,
+            None => {
+                eprintln!("warning: remote cache '{}' is not an http:// url", url);
+                return None;
+            }
+        };
+
+        let (authority, prefix) = match rest.find('/') {
+            Some(index) => (&rest[..index], rest[index..].trim_end_matches('/')),
+            None => (rest, ""),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().ok()?),
+            None => (authority, 80),
+        };
+
+        Some(RemoteCache {
+            host: host.to_string(),
+            port,
+            prefix: prefix.to_string(),
+            writable: mode == Some(RemoteCacheMode::ReadWrite),
+        })
+    }
+
+    fn request(
+        &self,
+        method: &str,
+        name: &str,
+        body: &[u8],
+    ) -> Result<(u16, Vec<u8>), std::io::Error> {
+        let address = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| std::io::Error::other("no address found"))?;
+
+        let mut stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+        write!(
+            stream,
+            "{} {}/{} HTTP/1.1\r\nHost: {}:{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            method,
+            self.prefix,
+            name,
+            self.host,
+            self.port,
+            body.len()
+        )?;
+        stream.write_all(body)?;
+
+        let mut response = vec![];
+        stream.read_to_end(&mut response)?;
+
+        // Split the status line and headers from the body
+        let end = response
+            .windows(4)
+            .position(|x| x == b"\r\n\r\n")
+            .ok_or_else(|| std::io::Error::other("invalid response"))?;
+        let head = String::from_utf8_lossy(&response[..end]).to_string();
+        let mut body = response.split_off(end + 4);
+
+        let status = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|x| x.parse().ok())
+            .ok_or_else(|| std::io::Error::other("invalid status line"))?;
+
+        // A response cut short must never end up in the cache as a truncated object
+        let chunked = get_header(&head, "transfer-encoding")
+            .map(|x| x.eq_ignore_ascii_case("chunked"))
+            .unwrap_or(false);
+        if chunked {
+            body = decode_chunked(&body)?;
+        } else if let Some(length) = get_header(&head, "content-length") {
+            let length: usize = length
+                .parse()
+                .map_err(|_| std::io::Error::other("invalid content length"))?;
+            if body.len() < length {
+                return Err(std::io::Error::other(format!(
+                    "response ended after {} of {} bytes",
+                    body.len(),
+                    length
+                )));
+            }
+            body.truncate(length);
+        }
+
+        Ok((status, body))
+    }
+
+    fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    fn try_request(&self, method: &str, name: &str, body: &[u8]) -> Option<(u16, Vec<u8>)> {
+        if UNREACHABLE.lock().unwrap().contains(&self.address()) {
+            return None;
+        }
+
+        // The build goes on without the remote cache when it can't be reached
+        match self.request(method, name, body) {
+            Ok(response) => Some(response),
+            Err(e) => {
+                eprintln!(
+                    "warning: remote cache {} can't be reached, continuing without it: {}",
+                    self.address(),
+                    e
+                );
+                UNREACHABLE.lock().unwrap().insert(self.address());
+                None
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Vec<u8>> {
+        match self.try_request("GET", name, &[])? {
+            (200, body) => Some(body),
+            _ => None,
+        }
+    }
+
+    pub fn put(&self, name: &str, contents: &[u8]) {
+        if !self.writable {
+            return;
+        }
+
+        if let Some((status, _)) = self.try_request("PUT", name, contents) {
+            if !(200..300).contains(&status) {
+                eprintln!(
+                    "warning: remote cache refused to store {} (status {})",
+                    name, status
+                );
+            }
+        }
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &[u8]) -> Result<(), std::io::Error> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    )?;
+    stream.write_all(body)
+}
+
+fn handle(stream: TcpStream, dir: &Path) -> Result<(), std::io::Error> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let path = parts.next().unwrap_or("").to_string();
+
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    // Any prefix is accepted, only the file name matters
+    let name = path.rsplit('/').next().unwrap_or("");
+    if !is_valid_name(name) {
+        return respond(&mut stream, "400 Bad Request", b"");
+    }
+    let entry = get_entry_path(dir, name);
+
+    match method.as_str() {
+        "GET" => match std::fs::read(&entry) {
+            Ok(contents) => respond(&mut stream, "200 OK", &contents),
+            Err(_) => respond(&mut stream, "404 Not Found", b""),
+        },
+        "PUT" => {
+            // Chunked uploads aren't supported, buldr itself always sends the length
+            let content_length = match content_length {
+                Some(length) if length <= MAX_ENTRY_SIZE => length,
+                Some(_) => return respond(&mut stream, "413 Payload Too Large", b""),
+                None => return respond(&mut stream, "411 Length Required", b""),
+            };
+
+            let mut contents = vec![0; content_length];
+            reader.read_exact(&mut contents)?;
+
+            // Write next to the entry first, so a concurrent GET never sees half a file
+            let temp = entry.with_extension(format!("tmp{}", std::process::id()));
+            std::fs::create_dir_all(entry.parent().unwrap())?;
+            std::fs::write(&temp, contents)?;
+            std::fs::rename(&temp, &entry)?;
+
+            respond(&mut stream, "201 Created", b"")
+        }
+        _ => respond(&mut stream, "405 Method Not Allowed", b""),
+    }
+}
+
+pub fn serve(address: &str, dir: &Path) -> Result<(), std::io::Error> {
+    let listener = TcpListener::bind(address)?;
+    println!(
+        "Serving {} on http://{}",
+        dir.display(),
+        listener.local_addr()?
+    );
+
+    serve_listener(listener, dir)
+}
+
+fn serve_listener(listener: TcpListener, dir: &Path) -> Result<(), std::io::Error> {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+
+        let dir = dir.to_path_buf();
+        std::thread::spawn(move || {
+            if let Err(e) = handle(stream, &dir) {
+                eprintln!("{}", e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "0123456789abcdef";
+
+    fn start_server(name: &str) -> (RemoteCache, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("buldr-{}-{}", name, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server_dir = dir.clone();
+        std::thread::spawn(move || serve_listener(listener, &server_dir));
+
+        let url = format!("http://127.0.0.1:{}/cache", port);
+        let cache = RemoteCache::new(&url, Some(RemoteCacheMode::ReadWrite)).unwrap();
+        (cache, dir)
+    }
+
+    #[test]
+    fn put_and_get() {
+        let (cache, dir) = start_server("put-and-get");
+        let name = format!("{}.o", KEY);
+
+        assert_eq!(cache.get(&name), None);
+
+        cache.put(&name, b"object");
+        assert_eq!(cache.get(&name), Some(b"object".to_vec()));
+        assert!(get_entry_path(&dir, &name).is_file());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn read_only_does_not_put() {
+        let (mut cache, dir) = start_server("read-only");
+        let name = format!("{}.o", KEY);
+        cache.writable = false;
+
+        cache.put(&name, b"object");
+        assert_eq!(cache.get(&name), None);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn unreachable_server_is_skipped() {
+        // Nothing listens on the port once the listener is gone
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let url = format!("http://127.0.0.1:{}", port);
+        let cache = RemoteCache::new(&url, Some(RemoteCacheMode::ReadWrite)).unwrap();
+
+        assert_eq!(cache.get(&format!("{}.o", KEY)), None);
+        assert!(UNREACHABLE.lock().unwrap().contains(&cache.address()));
+
+        // Later requests don't even try to connect
+        cache.put(&format!("{}.o", KEY), b"object");
+        assert_eq!(cache.get(&format!("{}.o", KEY)), None);
+    }
+
+    #[test]
+    fn server_refuses_large_and_unsized_uploads() {
+        let (cache, dir) = start_server("refuse");
+
+        let status = |headers: &str| {
+            let mut stream = TcpStream::connect((cache.host.as_str(), cache.port)).unwrap();
+            write!(stream, "PUT /{}.o HTTP/1.1\r\n{}\r\n", KEY, headers).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response.split_whitespace().nth(1).unwrap().to_string()
+        };
+
+        let too_large = format!("Content-Length: {}\r\n", MAX_ENTRY_SIZE + 1);
+        assert_eq!(status(&too_large), "413");
+        assert_eq!(status("Transfer-Encoding: chunked\r\n"), "411");
+        assert!(!get_entry_path(&dir, &format!("{}.o", KEY)).exists());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn truncated_response_is_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nobj").unwrap();
+        });
+
+        let url = format!("http://127.0.0.1:{}", port);
+        let cache = RemoteCache::new(&url, None).unwrap();
+        assert!(cache.request("GET", &format!("{}.o", KEY), &[]).is_err());
+    }
+
+    #[test]
+    fn decode_chunked_body() {
+        assert_eq!(
+            decode_chunked(b"3\r\nobj\r\n4;ext=1\r\nect!\r\n0\r\n\r\n").unwrap(),
+            b"object!".to_vec()
+        );
+        assert!(decode_chunked(b"a\r\nshort\r\n").is_err());
+        assert!(decode_chunked(b"zz\r\n").is_err());
+    }
+
+    #[test]
+    fn valid_names() {
+        assert!(is_valid_name(&format!("{}.o", KEY)));
+        assert!(is_valid_name(&format!("{}.stderr", KEY)));
+        assert!(!is_valid_name("../../etc/passwd"));
+        assert!(!is_valid_name(&format!("{}.txt", KEY)));
+        assert!(!is_valid_name("ab.o"));
+    }
+}
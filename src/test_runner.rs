@@ -0,0 +1,187 @@
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use termion::color;
+
+// How often a running test is checked for completion
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, PartialEq)]
+pub enum TestStatus {
+    Passed,
+    Failed(Option<i32>),
+    TimedOut,
+}
+
+#[derive(Debug)]
+pub struct TestResult {
+    pub name: String,
+    pub status: TestStatus,
+    pub output: String,
+    pub duration: Duration,
+}
+
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<String> {
+    // Pipes are drained on a separate thread so a chatty test can't block on a full pipe
+    thread::spawn(move || {
+        let mut output = String::new();
+        if let Some(mut pipe) = pipe {
+            let mut bytes = vec![];
+            pipe.read_to_end(&mut bytes).ok();
+            output = String::from_utf8_lossy(&bytes).to_string();
+        }
+        output
+    })
+}
+
+pub fn run_test(
+    name: &str,
+    mut command: Command,
+    timeout: Duration,
+) -> Result<TestResult, std::io::Error> {
+    let started = Instant::now();
+
+    // The test gets its own process group, so whatever it starts can be killed along with it
+    let mut child = command
+        .process_group(0)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+
+    // Wait for the test to finish, killing it when it takes too long
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break if status.success() {
+                TestStatus::Passed
+            } else {
+                TestStatus::Failed(status.code())
+            };
+        }
+
+        if started.elapsed() > timeout {
+            // Processes started by the test hold on to the pipes, the readers only finish
+            // once all of them are gone
+            Command::new("kill")
+                .args(["-KILL", "--", &format!("-{}", child.id())])
+                .status()
+                .ok();
+            child.kill().ok();
+            child.wait()?;
+            break TestStatus::TimedOut;
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    let mut output = stdout.join().unwrap();
+    output.push_str(&stderr.join().unwrap());
+
+    Ok(TestResult {
+        name: name.to_string(),
+        status,
+        output,
+        duration: started.elapsed(),
+    })
+}
+
+pub fn print_result(result: &TestResult) {
+    match result.status {
+        TestStatus::Passed => println!(
+            "test {} ... {}ok{}",
+            result.name,
+            color::Fg(color::Green),
+            color::Fg(color::Reset)
+        ),
+        TestStatus::Failed(code) => {
+            println!(
+                "test {} ... {}FAILED{} (exit code {})",
+                result.name,
+                color::Fg(color::Red),
+                color::Fg(color::Reset),
+                code.map(|x| x.to_string())
+                    .unwrap_or_else(|| String::from("none"))
+            );
+            print!("{}", result.output);
+        }
+        TestStatus::TimedOut => {
+            println!(
+                "test {} ... {}TIMEOUT{}",
+                result.name,
+                color::Fg(color::Red),
+                color::Fg(color::Reset)
+            );
+            print!("{}", result.output);
+        }
+    }
+}
+
+pub fn print_summary(results: &[TestResult]) {
+    let passed = results
+        .iter()
+        .filter(|x| x.status == TestStatus::Passed)
+        .count();
+
+    println!(
+        "test result: {} passed, {} failed",
+        passed,
+        results.len() - passed
+    );
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn write_junit(results: &[TestResult], path: &Path) -> Result<(), std::io::Error> {
+    let failures = results
+        .iter()
+        .filter(|x| x.status != TestStatus::Passed)
+        .count();
+    let time: f64 = results.iter().map(|x| x.duration.as_secs_f64()).sum();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuite name=\"buldr\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+        results.len(),
+        failures,
+        time
+    ));
+
+    for result in results {
+        xml.push_str(&format!(
+            "  <testcase name=\"{}\" classname=\"buldr\" time=\"{:.3}\"",
+            escape_xml(&result.name),
+            result.duration.as_secs_f64()
+        ));
+
+        let message = match result.status {
+            TestStatus::Passed => {
+                xml.push_str(" />\n");
+                continue;
+            }
+            TestStatus::Failed(Some(code)) => format!("exited with code {}", code),
+            TestStatus::Failed(None) => String::from("terminated by a signal"),
+            TestStatus::TimedOut => String::from("timed out"),
+        };
+
+        xml.push_str(&format!(
+            ">\n    <failure message=\"{}\">{}</failure>\n  </testcase>\n",
+            escape_xml(&message),
+            escape_xml(&result.output)
+        ));
+    }
+
+    xml.push_str("</testsuite>\n");
+    std::fs::write(path, xml)
+}
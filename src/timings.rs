@@ -0,0 +1,142 @@
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Amount of entries shown in each section of the timings report
+const REPORT_LIMIT: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimingKind {
+    Compile,
+    Link,
+}
+
+#[derive(Debug)]
+pub struct TimingEntry {
+    pub kind: TimingKind,
+    pub project: String,
+    pub name: String,
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+#[derive(Serialize)]
+struct TraceEvent<'a> {
+    name: &'a str,
+    cat: &'a str,
+    ph: &'a str,
+    ts: u128,
+    dur: u128,
+    pid: u32,
+    tid: u32,
+    args: HashMap<&'a str, &'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Trace<'a> {
+    trace_events: Vec<TraceEvent<'a>>,
+}
+
+pub struct Timings {
+    start: Instant,
+    entries: Vec<TimingEntry>,
+}
+
+impl Timings {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            entries: vec![],
+        }
+    }
+
+    pub fn record(&mut self, kind: TimingKind, project: &str, name: &str, started: Instant) {
+        self.entries.push(TimingEntry {
+            kind,
+            project: project.to_string(),
+            name: name.to_string(),
+            start: started.duration_since(self.start),
+            duration: started.elapsed(),
+        });
+    }
+
+    fn format_duration(duration: &Duration) -> String {
+        format!("{:>8.2}s", duration.as_secs_f64())
+    }
+
+    pub fn write_report(&self, output: &mut dyn Write) -> Result<(), std::io::Error> {
+        // Sort all compiled files from slowest to fastest
+        let mut files: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|x| x.kind == TimingKind::Compile)
+            .collect();
+        files.sort_by_key(|x| std::cmp::Reverse(x.duration));
+
+        // Sum up the compile and link time of every project
+        let mut projects: Vec<(&str, Duration)> = vec![];
+        for entry in &self.entries {
+            match projects.iter_mut().find(|(name, _)| *name == entry.project) {
+                Some((_, total)) => *total += entry.duration,
+                None => projects.push((&entry.project, entry.duration)),
+            }
+        }
+        projects.sort_by_key(|x| std::cmp::Reverse(x.1));
+
+        writeln!(output, "Slowest files:")?;
+        for entry in files.iter().take(REPORT_LIMIT) {
+            writeln!(
+                output,
+                "{} {:10} {}",
+                Self::format_duration(&entry.duration),
+                entry.project,
+                entry.name
+            )?;
+        }
+
+        writeln!(output, "Slowest projects:")?;
+        for (name, total) in projects.iter().take(REPORT_LIMIT) {
+            writeln!(output, "{} {}", Self::format_duration(total), name)?;
+        }
+
+        writeln!(
+            output,
+            "Total: {}",
+            Self::format_duration(&self.start.elapsed())
+        )
+    }
+
+    pub fn write_trace(&self, path: &Path) -> Result<(), std::io::Error> {
+        // Convert every entry to a Chrome trace "complete" event, grouped per project
+        let trace_events = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let mut args = HashMap::new();
+                args.insert("project", entry.project.as_str());
+
+                TraceEvent {
+                    name: &entry.name,
+                    cat: match entry.kind {
+                        TimingKind::Compile => "compile",
+                        TimingKind::Link => "link",
+                    },
+                    ph: "X",
+                    ts: entry.start.as_micros(),
+                    dur: entry.duration.as_micros(),
+                    pid: 1,
+                    tid: 1,
+                    args,
+                }
+            })
+            .collect();
+
+        std::fs::write(
+            path,
+            serde_json::to_string(&Trace { trace_events }).unwrap(),
+        )
+    }
+}
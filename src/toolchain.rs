@@ -0,0 +1,203 @@
+use crate::config::Config;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+
+pub const C_COMPILERS: &[&str] = &["clang", "gcc", "cc"];
+pub const CPP_COMPILERS: &[&str] = &["clang++", "g++", "c++"];
+pub const PACKERS: &[&str] = &["ar", "llvm-ar"];
+pub const CPP_EXTENSIONS: &[&str] = &["cpp", "cc", "cxx"];
+const CPP_HEADER_EXTENSIONS: &[&str] = &["hh", "hpp", "hxx"];
+
+pub struct Toolchain {
+    pub compiler: String,
+    pub linker: String,
+    pub packer: String,
+}
+
+pub fn is_cpp(path: &Path) -> bool {
+    // Sources and headers with these extensions need a C++ compiler
+    path.extension()
+        .and_then(|x| x.to_str())
+        .map(|x| CPP_EXTENSIONS.contains(&x) || CPP_HEADER_EXTENSIONS.contains(&x))
+        .unwrap_or(false)
+}
+
+pub fn find_program(name: &str) -> Option<PathBuf> {
+    // Paths are used as is, plain names are looked up in the PATH
+    if Path::new(name).components().count() > 1 {
+        return Some(PathBuf::from(name)).filter(|x| x.is_file());
+    }
+
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+    })
+}
+
+pub fn find_first_program(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .find(|x| find_program(x).is_some())
+        .map(|x| x.to_string())
+}
+
+fn from_env(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+}
+
+fn detect_program(variable: &str, programs: &[&str]) -> String {
+    // The environment wins, then the first program that is installed
+    from_env(variable)
+        .or_else(|| find_first_program(programs))
+        .unwrap_or_else(|| programs[0].to_string())
+}
+
+pub fn detect(cpp: bool) -> Toolchain {
+    // The C driver picks the language from the extension, a C++ driver would compile C as C++.
+    // Only linking C++ objects needs the C++ driver, for its standard library
+    let compiler = detect_program("CC", C_COMPILERS);
+    let linker = if cpp {
+        detect_program("CXX", CPP_COMPILERS)
+    } else {
+        compiler.clone()
+    };
+    let packer = detect_program("AR", PACKERS);
+
+    Toolchain {
+        compiler,
+        linker,
+        packer,
+    }
+}
+
+pub fn compiler_command(launcher: Option<&str>, compiler: &str) -> Command {
+    // Launchers like ccache take the compiler as their first argument
+    let launcher: Vec<&str> = launcher.iter().flat_map(|x| x.split_whitespace()).collect();
+    match launcher.split_first() {
+        Some((program, args)) => {
+            let mut command = Command::new(program);
+            command.args(args).arg(compiler);
+            command
+        }
+        None => Command::new(compiler),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompilerFamily {
+    Gcc,
+    Clang,
+}
+
+pub fn get_version(compiler: &str) -> String {
+    // Asking means running the compiler, so remember the answer for every compiler
+    static VERSIONS: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+    let versions = VERSIONS.get_or_init(|| Mutex::new(HashMap::new()));
+
+    if let Some(version) = versions.lock().unwrap().get(compiler) {
+        return version.clone();
+    }
+
+    let version = Command::new(compiler)
+        .arg("--version")
+        .output()
+        .map(|x| String::from_utf8_lossy(&x.stdout).to_string())
+        .unwrap_or_else(|_| compiler.to_string());
+
+    versions
+        .lock()
+        .unwrap()
+        .insert(compiler.to_string(), version.clone());
+    version
+}
+
+pub fn detect_family(compiler: &str) -> CompilerFamily {
+    // Names like `cc` don't say much, but the version output does
+    if get_version(compiler).contains("clang") {
+        CompilerFamily::Clang
+    } else {
+        CompilerFamily::Gcc
+    }
+}
+
+pub struct Check {
+    pub name: &'static str,
+    pub result: Result<(), String>,
+}
+
+fn run_step(program: &str, args: &[String], dir: &Path) -> Result<(), String> {
+    let output = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| format!("failed to run {}: {}", program, e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        let mut message = String::from_utf8_lossy(&output.stdout).to_string();
+        message.push_str(&String::from_utf8_lossy(&output.stderr));
+        Err(message)
+    }
+}
+
+pub fn check(config: &Config, cpp: bool, run: bool) -> Vec<Check> {
+    // Build a trivial program in a scratch directory, every step needs the one before it
+    let dir = std::env::temp_dir().join(format!("buldr-doctor-{}", std::process::id()));
+    let source = if cpp { "main.cpp" } else { "main.c" };
+
+    let mut checks = vec![];
+    let mut step = |name, program: &str, args: &[&str], opts: &Option<Vec<String>>| {
+        let mut args: Vec<String> = args.iter().map(|x| x.to_string()).collect();
+        args.extend(opts.iter().flatten().cloned());
+
+        let result = match checks.last() {
+            Some(Check { result: Err(_), .. }) => Err(String::from("skipped")),
+            _ => run_step(program, &args, &dir),
+        };
+        checks.push(Check { name, result });
+    };
+
+    match std::fs::create_dir_all(&dir)
+        .and_then(|_| std::fs::write(dir.join(source), "int main(void) { return 0; }\n"))
+    {
+        Ok(_) => {
+            // The configured options are used as well, they can break a toolchain too
+            let compile = ["-c", source, "-o", "main.o"];
+            step(
+                "compile",
+                config.compiler(),
+                &compile,
+                &config.compiler_opts,
+            );
+            step(
+                "link",
+                config.linker(),
+                &["main.o", "-o", "main"],
+                &config.linker_opts,
+            );
+
+            // Programs built for another target usually can't run here
+            if run {
+                step("run", "./main", &[], &None);
+            }
+
+            let mut packer_args = config.packer_opts.clone().unwrap_or_default();
+            packer_args.extend(["rcs", "libmain.a", "main.o"].iter().map(|x| x.to_string()));
+            step("archive", config.packer(), &[], &Some(packer_args));
+        }
+        Err(e) => checks.push(Check {
+            name: "scratch directory",
+            result: Err(e.to_string()),
+        }),
+    }
+
+    std::fs::remove_dir_all(&dir).ok();
+    checks
+}
@@ -0,0 +1,154 @@
+use crate::cache::CACHE_PATH;
+use crate::config::Config;
+use crate::glob;
+use crate::install::normalize;
+use crate::project::Project;
+use crate::{get_all_dependencies, load_config, COMPILE_COMMANDS_PATH};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+// How often the watched files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// How long the files have to stay unchanged before a build is started
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+type Snapshot = BTreeMap<PathBuf, SystemTime>;
+
+struct WatchSet {
+    paths: Vec<PathBuf>,
+    // Everything the build writes itself, watching it would start a new build after every build
+    ignored: Vec<PathBuf>,
+}
+
+fn get_ignored_paths(config: &Config) -> Vec<PathBuf> {
+    [
+        Path::new(&config.bin),
+        Path::new(&config.obj),
+        Path::new(CACHE_PATH),
+        Path::new(COMPILE_COMMANDS_PATH),
+    ]
+    .iter()
+    .map(|x| normalize(x))
+    .collect()
+}
+
+pub fn get_watched_paths(projects: &[&Project]) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = vec![];
+
+    for project in projects {
+        paths.extend(project.src.iter().cloned());
+        paths.extend(project.include.iter().flatten().map(PathBuf::from));
+
+        // Inputs of generators can live anywhere
+        for generator in project.generate.iter().flatten() {
+            paths.extend(generator.inputs.iter().flat_map(|x| glob::expand(x)));
+        }
+    }
+
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+fn snapshot(watch_set: &WatchSet) -> Snapshot {
+    let mut snapshot = BTreeMap::new();
+
+    for path in &watch_set.paths {
+        let entries = WalkDir::new(path).into_iter().filter_entry(|e| {
+            let path = normalize(e.path());
+            !watch_set.ignored.iter().any(|x| path.starts_with(x))
+        });
+        for entry in entries.filter_map(|e| e.ok()) {
+            if let Ok(modified) = entry.path().metadata().and_then(|x| x.modified()) {
+                snapshot.insert(entry.path().to_path_buf(), modified);
+            }
+        }
+    }
+
+    snapshot
+}
+
+fn wait_for_change(watch_set: &WatchSet, previous: &Snapshot) -> Snapshot {
+    loop {
+        thread::sleep(POLL_INTERVAL);
+
+        let mut current = snapshot(watch_set);
+        if &current == previous {
+            continue;
+        }
+
+        // Editors often write a file in several steps, so wait until things settle down
+        loop {
+            thread::sleep(DEBOUNCE);
+
+            let next = snapshot(watch_set);
+            if next == current {
+                return current;
+            }
+            current = next;
+        }
+    }
+}
+
+fn get_watch_set(build_file: &str, project: Option<&str>) -> Result<WatchSet, std::io::Error> {
+    // The sources are the same for every target
+    let config = load_config(build_file, None)?;
+
+    let projects = match project {
+        Some(name) => match config.projects.iter().find(|x| x.name == name) {
+            Some(project) => get_all_dependencies(&config.projects, project),
+            None => {
+                eprintln!("No project found with name '{}'", name);
+                std::process::exit(1);
+            }
+        },
+        None => config.projects.iter().collect(),
+    };
+
+    // The build file itself is watched as well so the config can be reloaded
+    let mut paths = get_watched_paths(&projects);
+    paths.push(PathBuf::from(build_file));
+    Ok(WatchSet {
+        paths,
+        ignored: get_ignored_paths(&config.config),
+    })
+}
+
+pub fn watch(
+    build_file: &str,
+    project: Option<&str>,
+    build_args: &[String],
+) -> Result<(), std::io::Error> {
+    let exe = std::env::current_exe()?;
+    let mut watch_set = get_watch_set(build_file, project)?;
+    let build_file_path = PathBuf::from(build_file);
+
+    loop {
+        // Every build runs in its own process so a failing build doesn't stop the watcher
+        let status = Command::new(&exe).args(build_args).status()?;
+
+        // Only changes made after the build count, not the files the build itself wrote
+        let current = snapshot(&watch_set);
+        if status.success() {
+            println!("Waiting for changes");
+        } else {
+            eprintln!("Build failed, waiting for changes");
+        }
+
+        let next = wait_for_change(&watch_set, &current);
+
+        // A changed build file can change what has to be watched
+        if next.get(&build_file_path) != current.get(&build_file_path) {
+            println!("Reloading {}", build_file);
+            match get_watch_set(build_file, project) {
+                Ok(new_watch_set) => watch_set = new_watch_set,
+                Err(e) => eprintln!("Failed to reload config: {}", e),
+            }
+        }
+    }
+}